@@ -0,0 +1,9 @@
+-- Add up migration script here
+CREATE TABLE scheduled_task (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    chat_id INTEGER NOT NULL,
+    message_id INTEGER NOT NULL,
+    action TEXT NOT NULL,
+    run_at DATETIME NOT NULL
+);
+CREATE INDEX scheduled_task_run_at_idx ON scheduled_task (run_at);
@@ -0,0 +1,13 @@
+-- Add up migration script here
+CREATE TABLE archive_request (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    gallery_id INTEGER NOT NULL,
+    token TEXT NOT NULL,
+    requested_at DATETIME NOT NULL,
+    resolution TEXT NOT NULL,
+    success BOOLEAN NOT NULL,
+    message TEXT,
+    cost INTEGER
+);
+CREATE INDEX archive_request_gallery_id_idx ON archive_request (gallery_id);
+CREATE INDEX archive_request_requested_at_idx ON archive_request (requested_at);
@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- 频道消息渲染结果的 hash，重新渲染时只修改内容有变化的消息
+ALTER TABLE message ADD COLUMN text_hash TEXT;
+-- catbox 专辑地址，重新渲染消息时需要用到
+ALTER TABLE telegraph ADD COLUMN album_url TEXT;
@@ -0,0 +1,20 @@
+-- Add up migration script here
+-- 画廊的全文索引，rowid 为画廊 ID，tags 为每行一个的 namespace:tag
+-- 使用 trigram 分词，以便搜索没有空格分隔的中文和日文标题
+CREATE VIRTUAL TABLE gallery_fts USING fts5(title, title_jp, tags, tokenize = 'trigram');
+
+-- 形如 nekogen | miyauchi takeshi 的 tag 会被展开为两行
+INSERT INTO gallery_fts (rowid, title, title_jp, tags)
+SELECT
+    gallery.id,
+    gallery.title,
+    gallery.title_jp,
+    (
+        SELECT group_concat(
+            ns.key || ':' || replace(tag.value, ' | ', char(10) || ns.key || ':'),
+            char(10)
+        )
+        FROM json_each(CASE WHEN json_valid(gallery.tags) THEN gallery.tags ELSE '{}' END) AS ns,
+            json_each(ns.value) AS tag
+    )
+FROM gallery;
@@ -0,0 +1,7 @@
+-- Add up migration script here
+-- 不满足上传规则的画廊，扫描时在一段时间内不再获取画廊信息
+CREATE TABLE rejected_gallery (
+    id INTEGER PRIMARY KEY NOT NULL,
+    reason TEXT NOT NULL,
+    rejected_at DATETIME NOT NULL
+);
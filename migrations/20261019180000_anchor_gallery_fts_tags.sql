@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- 首尾加上换行，搜索 tag 时按整行匹配，避免 male:x 匹配到 female:x
+UPDATE gallery_fts SET tags = char(10) || ifnull(tags, '') || char(10);
@@ -2,16 +2,17 @@ use std::env;
 use std::time::Duration;
 
 use anyhow::Result;
-use chrono::Timelike;
-use clap::Parser;
+use chrono::{Local, NaiveDateTime, Utc};
+use clap::{Parser, Subcommand};
 use exloli_next::config::Config;
-use exloli_next::ehentai::EhClient;
+use exloli_next::database::ArchiveRequestEntity;
+use exloli_next::ehentai::{ArchiveResolution, ArchiveResult, EhClient, EhGalleryUrl};
 use futures::StreamExt;
 use glob::glob;
-use tracing::{info, warn};
+use tracing::{error, info, warn};
 
 #[derive(Parser)]
-struct Args {
+struct Cli {
     /// 配置文件路径
     #[clap(short, long, default_value = "./config.toml")]
     config: String,
@@ -21,14 +22,33 @@ struct Args {
     /// H@H 下载位置
     #[clap(short, long, default_value = "/mnt/ehentai/download/convert")]
     download: String,
+    /// 请求的分辨率，可选 780、980、1280、1600、2400、org，默认使用配置文件中的设置
+    #[clap(short, long)]
+    resolution: Option<ArchiveResolution>,
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// 遍历收藏夹，请求归档尚未请求过的画廊，不指定子命令时的默认行为
+    Run,
+    /// 守护模式，按照配置中的间隔反复遍历收藏夹
+    Daemon,
+    /// 查看归档请求的统计和失败记录
+    Status,
+    /// 重新请求归档失败过的画廊
+    RetryFailed,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::parse();
+    let args = Cli::parse();
 
     let config = Config::new(&args.config)?;
 
+    // NOTE: 全局数据库连接需要用这个变量初始化
+    env::set_var("DATABASE_URL", &config.database_url);
     env::set_var("RUST_LOG", &config.log_level);
 
     tracing_subscriber::FmtSubscriber::builder()
@@ -36,32 +56,153 @@ async fn main() -> Result<()> {
         .try_init()
         .unwrap();
 
+    let command = args.command.as_ref().unwrap_or(&Command::Run);
+    if let Command::Status = command {
+        return status().await;
+    }
+
     let ehentai = EhClient::new(&config.exhentai.cookie).await?;
+    match command {
+        Command::Run => run(&ehentai, &config, &args).await,
+        Command::Daemon => daemon(&ehentai, config, &args).await,
+        Command::RetryFailed => retry_failed(&ehentai, &config, &args).await,
+        Command::Status => unreachable!(),
+    }
+}
+
+async fn daemon(ehentai: &EhClient, mut config: Config, args: &Cli) -> Result<()> {
+    loop {
+        if let Err(err) = run(ehentai, &config, args).await {
+            error!("遍历收藏夹失败: {:?}", err);
+        }
+        info!("等待 {:?} 后再次遍历收藏夹", config.archiver.poll_interval);
+        tokio::time::sleep(config.archiver.poll_interval).await;
+        // 每轮重新读取配置，这样修改时间段等设置后不需要重启
+        match Config::new(&args.config) {
+            Ok(new) => config = new,
+            Err(err) => warn!("重新加载配置失败，继续使用旧配置: {:#}", err),
+        }
+    }
+}
+
+async fn run(ehentai: &EhClient, config: &Config, args: &Cli) -> Result<()> {
     let params = [("favcat", args.favcat)];
     let stream = ehentai.page_iter("https://exhentai.org/favorites.php", &params);
     tokio::pin!(stream);
     while let Some(gallery) = stream.next().await {
+        // 失败的画廊也不再自动重试，以免每次遍历都重复请求
+        if ArchiveRequestEntity::is_requested(gallery.id()).await? {
+            info!("跳过: {}", gallery.url());
+            continue;
+        }
+        // 兼容没有请求记录的旧画廊
         if glob(&format!("{}/*[[]{}]", args.download, gallery.id()))?.next().is_some() {
             info!("跳过: {}", gallery.url());
             continue;
         }
-        info!("请求下载: {}", gallery.url());
-        if let Err(err) = ehentai.archive_gallery(&gallery).await {
-            warn!("下载失败: {}", err);
-        } else {
-            tokio::time::sleep(sleep_time()).await;
+        if reached_daily_limit(config).await? {
+            return Ok(());
         }
+        let resolution = args.resolution.unwrap_or(config.archiver.resolution_of(args.favcat));
+        request(ehentai, config, &gallery, resolution).await?;
         tokio::time::sleep(Duration::from_secs(5)).await;
     }
     Ok(())
 }
 
-fn sleep_time() -> Duration {
-    let now = chrono::Local::now();
-    // 2:30 ~ 8:30
-    if now.hour() >= 2 && now.hour() <= 8 {
-        Duration::from_secs(20 * 60)
+async fn retry_failed(ehentai: &EhClient, config: &Config, args: &Cli) -> Result<()> {
+    let failed = ArchiveRequestEntity::list_failed().await?;
+    info!("失败的画廊数量：{}", failed.len());
+    for record in failed {
+        if reached_daily_limit(config).await? {
+            return Ok(());
+        }
+        let gallery =
+            format!("https://exhentai.org/g/{}/{}/", record.gallery_id, record.token).parse()?;
+        // 默认使用上次请求的分辨率重试
+        let resolution = args
+            .resolution
+            .or_else(|| record.resolution.parse().ok())
+            .unwrap_or(config.archiver.resolution);
+        request(ehentai, config, &gallery, resolution).await?;
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    }
+    Ok(())
+}
+
+/// 检查今天（本地时间）的请求次数是否已经达到上限
+async fn reached_daily_limit(config: &Config) -> Result<bool> {
+    let limit = match config.archiver.daily_limit {
+        Some(limit) => limit,
+        None => return Ok(false),
+    };
+    let count = ArchiveRequestEntity::count_since(today_start()).await?;
+    if count as u32 >= limit {
+        info!("今天已经请求了 {} 次，达到每日上限 {}", count, limit);
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// 本地时间今天零点对应的 UTC 时间
+fn today_start() -> NaiveDateTime {
+    Local::now()
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .and_then(|t| t.and_local_timezone(Local).earliest())
+        .map(|t| t.naive_utc())
+        .unwrap_or_else(|| Utc::now().naive_utc() - chrono::Duration::days(1))
+}
+
+async fn status() -> Result<()> {
+    let stats = ArchiveRequestEntity::stats().await?;
+    println!("已归档画廊：{}", stats.success);
+    println!("失败画廊：{}", stats.failed);
+    println!("累计花费：{} GP", stats.cost);
+    println!("最近 24 小时请求次数：{}", stats.recent);
+    let failed = ArchiveRequestEntity::list_failed().await?;
+    if !failed.is_empty() {
+        println!("\n失败记录：");
+    }
+    for record in failed {
+        println!(
+            "{} https://exhentai.org/g/{}/{}/ {}",
+            record.requested_at.format("%Y-%m-%d %H:%M"),
+            record.gallery_id,
+            record.token,
+            record.message.unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+/// 请求归档并记录结果，成功后会等待一段时间，避免请求过于频繁
+async fn request(
+    ehentai: &EhClient,
+    config: &Config,
+    gallery: &EhGalleryUrl,
+    resolution: ArchiveResolution,
+) -> Result<()> {
+    info!("请求下载: {} {}", gallery.url(), resolution);
+    let result = ehentai.archive_gallery(gallery, resolution, config.archiver.max_cost).await;
+    let (success, message, cost) = match result {
+        Ok(ArchiveResult::Requested { cost, .. }) => (true, None, Some(cost as i64)),
+        Ok(ArchiveResult::Unavailable { .. }) => (false, Some("该分辨率不可用".to_string()), None),
+        Ok(ArchiveResult::OverBudget { cost, budget, .. }) => {
+            (false, Some(format!("需要 {cost} GP，超出预算 {budget} GP")), None)
+        }
+        Err(err) => (false, Some(err.to_string()), None),
+    };
+    let (id, token) = (gallery.id(), gallery.token());
+    ArchiveRequestEntity::create(id, token, resolution.as_str(), success, message.as_deref(), cost)
+        .await?;
+    if let Some(message) = message {
+        warn!("下载失败: {}", message);
     } else {
-        Duration::from_secs(60 * 60)
+        info!("请求成功，花费 {} GP", cost.unwrap_or_default());
+        let delay = config.archiver.delay_at(Local::now().time());
+        info!("等待 {:?}", delay);
+        tokio::time::sleep(delay).await;
     }
+    Ok(())
 }
@@ -1,17 +1,122 @@
 use std::env;
+use std::path::PathBuf;
+use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use chrono::NaiveDate;
+use clap::{Args, Parser, Subcommand};
 use exloli_next::bot::start_dispatcher;
-use exloli_next::config::{Config, CHANNEL_ID};
-use exloli_next::ehentai::EhClient;
+use exloli_next::config::{Config, SharedConfig, CHANNEL_ID};
+use exloli_next::database::{
+    self, get_connection_pool, BackupFormat, GalleryEntity, RejectedGalleryEntity,
+};
+use exloli_next::ehentai::{EhClient, EhGalleryUrl};
+use exloli_next::import::{ImportSource, Sidecar};
 use exloli_next::tags::EhTagTransDB;
 use exloli_next::uploader::ExloliUploader;
 use teloxide::prelude::*;
 use teloxide::types::ParseMode;
+use tracing::info;
+
+#[derive(Parser)]
+struct Cli {
+    /// 配置文件路径
+    #[clap(short, long, default_value = "./config.toml")]
+    config: String,
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// 启动 bot 并定时扫描 E 站，不指定子命令时的默认行为
+    Run,
+    /// 根据 E 站 URL 上传一个指定画廊，如果已存在，则重新上传
+    Upload {
+        url: EhGalleryUrl,
+        #[clap(flatten)]
+        dry_run: DryRunArgs,
+    },
+    /// 从本地文件夹、ZIP 或 CBZ 文件导入画廊，图片按文件名的自然顺序排列
+    Import {
+        /// 文件夹或压缩包路径
+        path: PathBuf,
+        /// 从 E 站获取画廊信息
+        #[clap(long)]
+        url: Option<EhGalleryUrl>,
+        /// 从 JSON 文件读取画廊信息，默认使用文件夹或压缩包中的 info.json，或者压缩包旁边的同名 JSON 文件
+        #[clap(long, conflicts_with = "url")]
+        meta: Option<PathBuf>,
+        #[clap(flatten)]
+        dry_run: DryRunArgs,
+    },
+    /// 根据 E 站 URL 更新一个已上传的画廊
+    Update { url: EhGalleryUrl },
+    /// 检测并补档 80 分以上或最近两个月的本子的预览
+    Recheck,
+    /// 执行数据库迁移
+    Migrate,
+    /// 导出或导入数据库
+    #[clap(subcommand)]
+    Db(DbCommand),
+    /// 检查配置文件是否正确
+    CheckConfig,
+    /// 根据配置文件扫描 E 站
+    Scan {
+        /// 只扫描一次，不定时重复
+        #[clap(long)]
+        once: bool,
+        #[clap(flatten)]
+        dry_run: DryRunArgs,
+    },
+}
+
+#[derive(Subcommand)]
+enum DbCommand {
+    /// 将画廊、页面、图片、投票和验证记录导出到指定目录，每个表一个文件
+    Export {
+        /// 导出目录
+        dir: PathBuf,
+        /// 导出格式，可选 jsonl、csv
+        #[clap(short, long, default_value = "jsonl")]
+        format: BackupFormat,
+        /// 只导出在此日期及之后发布的画廊，格式为 2024-01-01
+        #[clap(long)]
+        since: Option<NaiveDate>,
+        /// 只导出在此日期及之前发布的画廊，格式为 2024-12-31
+        #[clap(long)]
+        until: Option<NaiveDate>,
+    },
+    /// 从导出目录导入数据，已经存在的记录会被跳过，可以重复执行
+    Import {
+        /// 导出目录
+        dir: PathBuf,
+    },
+}
+
+#[derive(Args)]
+struct DryRunArgs {
+    /// 试运行，只生成消息和需要上传的页面列表，不上传图片、不发送消息、不写入数据库
+    #[clap(long)]
+    dry_run: bool,
+    /// 将试运行结果私聊发送给指定用户，而不是输出到标准输出
+    #[clap(long, requires = "dry_run")]
+    report_to: Option<i64>,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let config = Config::new("./config.toml")?;
+    let args = Cli::parse();
+    let command = args.command.unwrap_or(Command::Run);
+
+    if let Command::CheckConfig = command {
+        Config::new(&args.config)?;
+        println!("配置检查通过：{}", args.config);
+        return Ok(());
+    }
+
+    let shared_config = SharedConfig::new(&args.config)?;
+    let config = shared_config.get();
     CHANNEL_ID.set(config.telegram.channel_id.to_string()).unwrap();
 
     // NOTE: 全局数据库连接需要用这个变量初始化
@@ -23,26 +128,99 @@ async fn main() -> Result<()> {
         .try_init()
         .unwrap();
 
-    let trans = EhTagTransDB::new(&config.exhentai.trans_file);
+    if let Command::Migrate = command {
+        get_connection_pool(&config.database_url).await.close().await;
+        info!("数据库迁移完毕");
+        return Ok(());
+    }
+
+    if let Command::Db(command) = command {
+        let counts = match command {
+            DbCommand::Export { dir, format, since, until } => {
+                let counts = database::export(&dir, format, since, until).await?;
+                counts.into_iter().map(|(table, count)| (table, count as u64)).collect()
+            }
+            DbCommand::Import { dir } => database::import(&dir).await?,
+        };
+        for (table, count) in counts {
+            println!("{table}: {count}");
+        }
+        return Ok(());
+    }
+
+    let mut trans = EhTagTransDB::new(&config.exhentai.trans_file);
+    if let Some(file) = &config.exhentai.trans_override {
+        trans = trans.with_overrides(file);
+    }
+    // 重新加载配置时，一并重新加载翻译覆盖文件，并重新检查之前不满足上传规则的画廊
+    let shared_config = shared_config.with_reload_hook({
+        let trans = trans.clone();
+        Arc::new(move || {
+            let trans = trans.clone();
+            Box::pin(async move {
+                let cleared = RejectedGalleryEntity::clear().await;
+                trans.reload_overrides()?;
+                cleared?;
+                Ok(())
+            })
+        })
+    });
     let ehentai = EhClient::new(&config.exhentai.cookie).await?;
+
     let bot = Bot::new(&config.telegram.token)
         .throttle(Default::default())
         .parse_mode(ParseMode::Html)
         .cache_me();
-    let uploader =
-        ExloliUploader::new(config.clone(), ehentai.clone(), bot.clone(), trans.clone()).await?;
-
-    let t1 = {
-        let uploader = uploader.clone();
-        tokio::spawn(async move { uploader.start().await })
-    };
-    let t2 = {
-        let trans = trans.clone();
-        tokio::spawn(async move { start_dispatcher(config, uploader, bot, trans).await })
-    };
-    let t3 = tokio::spawn(async move { trans.start().await });
+    let mut uploader =
+        ExloliUploader::new(shared_config.clone(), ehentai.clone(), bot.clone(), trans.clone())
+            .await?;
+    if let Command::Upload { dry_run, .. }
+    | Command::Import { dry_run, .. }
+    | Command::Scan { dry_run, .. } = &command
+    {
+        if dry_run.dry_run {
+            uploader = uploader.with_dry_run(dry_run.report_to.map(ChatId));
+        }
+    }
+
+    match command {
+        Command::Upload { url, .. } => uploader.try_upload(&url, false).await?,
+        Command::Import { path, url, meta, .. } => {
+            let source = ImportSource::open(&path)?;
+            let gallery = match (url, meta.as_deref().or(source.sidecar())) {
+                (Some(url), _) => ehentai.get_gallery(&url).await?,
+                (None, Some(meta)) => Sidecar::load(meta)?.into_gallery()?,
+                (None, None) => bail!("找不到画廊信息，请通过 --url 或 --meta 指定"),
+            };
+            uploader.import(gallery, &source).await?;
+        }
+        Command::Update { url } => {
+            let gallery = GalleryEntity::get(url.id()).await?.context("找不到画廊")?;
+            uploader.recheck(vec![gallery]).await?;
+            uploader.try_update(&url, false).await?;
+        }
+        Command::Recheck => uploader.recheck(vec![]).await?,
+        Command::Scan { once: true, .. } => uploader.check().await,
+        Command::Scan { once: false, .. } => uploader.start().await,
+        Command::Run => {
+            let t1 = {
+                let uploader = uploader.clone();
+                tokio::spawn(async move { uploader.start().await })
+            };
+            let t2 = {
+                let trans = trans.clone();
+                let shared_config = shared_config.clone();
+                tokio::spawn(
+                    async move { start_dispatcher(shared_config, uploader, bot, trans).await },
+                )
+            };
+            let t3 = tokio::spawn(async move { trans.start().await });
+            let t4 = tokio::spawn(async move { shared_config.watch().await });
 
-    tokio::try_join!(t1, t2, t3)?;
+            tokio::try_join!(t1, t2, t3, t4)?;
+        }
+        Command::Migrate | Command::CheckConfig | Command::Db(_) => unreachable!(),
+    }
 
     Ok(())
 }
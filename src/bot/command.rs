@@ -17,6 +17,16 @@ pub enum AdminCommand {
     ReUpload,
     #[command(description = "检测并补档 80 分以上或最近两个月的本子的预览")]
     ReCheck,
+    #[command(description = "重新加载配置文件和翻译覆盖文件")]
+    Reload,
+    #[command(description = "列出最近 $1 天（默认 30 天）的画廊中没有翻译的 tag")]
+    Untranslated(String),
+    #[command(
+        description = "按照当前的翻译和模板重新渲染频道消息，参数为 all、开始日期 [结束日期] 或画廊 ID"
+    )]
+    ReRender(String),
+    #[command(description = "根据 E 站 URL 检查一个画廊是否满足上传规则")]
+    TestRule(EhGalleryUrl),
 }
 
 #[derive(BotCommands, Clone, PartialEq, Debug)]
@@ -33,6 +43,22 @@ pub enum PublicCommand {
         parse_with = "split"
     )]
     Best(u16, u16),
+    #[command(description = "查询 tag 的翻译和介绍，回复频道消息时查询该画廊的所有 tag")]
+    Tag(String),
+    #[command(
+        description = "搜索画廊，支持 关键词、artist:xxx、-tag 以及 score>=80，多个条件用空格分隔"
+    )]
+    Search(String),
+    #[command(
+        description = "随机推荐一本高分画廊，条件和 /search 相同，最后的数字为最低分数（默认 80）"
+    )]
+    Random(String),
+    #[command(description = "频道统计数据，参数为 chart 时发送分数分布图")]
+    Stats(String),
+    #[command(description = "查看自己的投票记录")]
+    MyVotes,
+    #[command(description = "根据自己的投票记录推荐还没有投过票的画廊")]
+    Recommend,
     #[command(description = "想和本 bot 斗斗吗？")]
     Challenge,
     #[command(description = "pong~")]
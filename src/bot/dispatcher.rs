@@ -1,27 +1,30 @@
 use std::time::Duration;
 
 use teloxide::prelude::*;
+use tracing::error;
 
 use super::filter::{filter_callbackdata, filter_channel_msg};
 use super::handlers::*;
 use super::utils::{ChallengeLocker, ChallengeProvider, RateLimiter};
 use super::Bot;
 use crate::bot::scheduler::Scheduler;
-use crate::config::Config;
+use crate::config::SharedConfig;
 use crate::tags::EhTagTransDB;
 use crate::uploader::ExloliUploader;
 
 pub async fn start_dispatcher(
-    config: Config,
+    config: SharedConfig,
     ehentai: ExloliUploader,
     bot: Bot,
     trans: EhTagTransDB,
 ) {
+    // NOTE: 每次处理更新时都取一份当前配置的快照，这样配置重新加载后可以立即生效
     let handler = dptree::entry()
+        .map(|config: SharedConfig| config.get())
         .branch(
             Update::filter_message()
                 .branch(admin_command_handler())
-                .branch(public_command_handler(config.clone()))
+                .branch(public_command_handler(config.get()))
                 .branch(filter_channel_msg().endpoint(custom_pool_sender)),
         )
         .branch(
@@ -29,6 +32,7 @@ pub async fn start_dispatcher(
                 .chain(filter_callbackdata())
                 .chain(callback_query_handler()),
         )
+        .branch(Update::filter_inline_query().endpoint(inline_query_handler))
         .branch(Update::filter_chat_join_request().endpoint(join_request_handler));
 
     // 限制每 60 秒只能进行 10 次操作
@@ -39,6 +43,9 @@ pub async fn start_dispatcher(
     let challenge_provider = ChallengeProvider::new();
 
     let scheduler = Scheduler::new(bot.clone());
+    if let Err(err) = scheduler.restore().await {
+        error!("恢复计划任务失败：{}", err);
+    }
 
     Dispatcher::builder(bot, handler)
         .dependencies(dptree::deps![
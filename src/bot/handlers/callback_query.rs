@@ -3,10 +3,14 @@ use teloxide::dispatching::DpHandlerDescription;
 use teloxide::dptree::case;
 use teloxide::prelude::*;
 use teloxide::utils::html::{link, user_mention};
+use teloxide::{ApiError, RequestError};
 use tracing::info;
 
 use super::utils::gallery_preview_url;
-use crate::bot::handlers::{cmd_best_keyboard, cmd_best_text, poll_keyboard};
+use crate::bot::handlers::{
+    cmd_best_keyboard, cmd_best_text, cmd_myvotes_text, cmd_random_text, cmd_search_text,
+    poll_keyboard,
+};
 use crate::bot::utils::{CallbackData, ChallengeLocker, RateLimiter};
 use crate::bot::Bot;
 use crate::config::Config;
@@ -19,6 +23,9 @@ pub fn callback_query_handler() -> Handler<'static, DependencyMap, Result<()>, D
     dptree::entry()
         .branch(case![CallbackData::VoteForPoll(poll, option)].endpoint(callback_vote_for_poll))
         .branch(case![CallbackData::Challenge(id, artist)].endpoint(callback_challenge))
+        .branch(case![CallbackData::SearchPage(page)].endpoint(callback_search_page))
+        .branch(case![CallbackData::Random].endpoint(callback_random))
+        .branch(case![CallbackData::MyVotesPage(page)].endpoint(callback_myvotes_page))
         .endpoint(callback_change_page)
 }
 
@@ -122,3 +129,88 @@ async fn callback_change_page(
 
     Ok(())
 }
+
+async fn callback_search_page(
+    bot: Bot,
+    query: CallbackQuery,
+    trans: EhTagTransDB,
+    cfg: Config,
+    page: i32,
+) -> Result<()> {
+    let message = query.message.context("消息过旧")?;
+    // 搜索条件太长，无法放进回调数据中，因此从 /search 指令中读取
+    let command =
+        message.reply_to_message().and_then(|msg| msg.text()).context("找不到搜索条件")?;
+    let search = command.split_once(char::is_whitespace).map(|(_, s)| s).unwrap_or_default();
+    info!("{}: <- search {} {}", query.from.id, page, search);
+
+    let (text, keyboard) = cmd_search_text(search, page, &trans, cfg.telegram.channel_id).await?;
+    let mut edit =
+        bot.edit_message_text(message.chat.id, message.id, text).disable_web_page_preview(true);
+    if let Some(keyboard) = keyboard {
+        edit = edit.reply_markup(keyboard);
+    }
+    edit.await?;
+    bot.answer_callback_query(query.id).await?;
+    Ok(())
+}
+
+async fn callback_random(
+    bot: Bot,
+    query: CallbackQuery,
+    trans: EhTagTransDB,
+    limiter: RateLimiter,
+    cfg: Config,
+) -> Result<()> {
+    if let Some(d) = limiter.insert(query.from.id) {
+        bot.answer_callback_query(query.id)
+            .text(format!("操作频率过高，请等待 {} 秒后再试", d.as_secs()))
+            .show_alert(true)
+            .await?;
+        return Ok(());
+    }
+    let message = query.message.context("消息过旧")?;
+    let command =
+        message.reply_to_message().and_then(|msg| msg.text()).context("找不到筛选条件")?;
+    let args = command.split_once(char::is_whitespace).map(|(_, s)| s).unwrap_or_default();
+    info!("{}: <- random {}", query.from.id, args);
+
+    let (text, keyboard) = cmd_random_text(args, &trans, cfg.telegram.channel_id).await?;
+    let mut edit = bot.edit_message_text(message.chat.id, message.id, text);
+    if let Some(keyboard) = keyboard {
+        edit = edit.reply_markup(keyboard);
+    }
+    // 只有一个符合条件的画廊时，内容不会变化，此时修改会报错 MessageNotModified
+    match edit.await {
+        Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => (),
+        Err(err) => return Err(err.into()),
+    }
+    bot.answer_callback_query(query.id).await?;
+    Ok(())
+}
+
+async fn callback_myvotes_page(
+    bot: Bot,
+    query: CallbackQuery,
+    cfg: Config,
+    page: i32,
+) -> Result<()> {
+    let message = query.message.context("消息过旧")?;
+    // 投票记录属于发送 /myvotes 的用户，其他人不能翻页
+    let owner = message.reply_to_message().and_then(|msg| msg.from()).map(|user| user.id);
+    if owner != Some(query.from.id) {
+        bot.answer_callback_query(query.id).text("只能查看自己的投票记录").show_alert(true).await?;
+        return Ok(());
+    }
+    info!("{}: <- myvotes {}", query.from.id, page);
+
+    let (text, keyboard) = cmd_myvotes_text(query.from.id, page, cfg.telegram.channel_id).await?;
+    let mut edit =
+        bot.edit_message_text(message.chat.id, message.id, text).disable_web_page_preview(true);
+    if let Some(keyboard) = keyboard {
+        edit = edit.reply_markup(keyboard);
+    }
+    edit.await?;
+    bot.answer_callback_query(query.id).await?;
+    Ok(())
+}
@@ -1,16 +1,25 @@
 use anyhow::{Context, Result};
+use std::time::Duration;
+
+use chrono::Utc;
+use indexmap::IndexMap;
 use teloxide::dispatching::DpHandlerDescription;
 use teloxide::dptree::case;
 use teloxide::prelude::*;
 use teloxide::types::MessageId;
-use tracing::info;
+use teloxide::utils::html::escape;
+use tokio::sync::watch;
+use tokio::time;
+use tracing::{error, info};
 
 use crate::bot::command::AdminCommand;
 use crate::bot::filter::filter_admin_msg;
 use crate::bot::Bot;
+use crate::config::SharedConfig;
 use crate::database::{GalleryEntity, MessageEntity};
 use crate::ehentai::EhGalleryUrl;
-use crate::uploader::ExloliUploader;
+use crate::tags::EhTagTransDB;
+use crate::uploader::{ExloliUploader, RerenderFilter, RerenderProgress};
 use crate::{reply_to, try_with_reply};
 
 pub fn admin_command_handler() -> Handler<'static, DependencyMap, Result<()>, DpHandlerDescription>
@@ -22,6 +31,103 @@ pub fn admin_command_handler() -> Handler<'static, DependencyMap, Result<()>, Dp
         .branch(case![AdminCommand::Erase].endpoint(cmd_delete))
         .branch(case![AdminCommand::ReCheck].endpoint(cmd_recheck))
         .branch(case![AdminCommand::ReUpload].endpoint(cmd_reupload))
+        .branch(case![AdminCommand::Reload].endpoint(cmd_reload))
+        .branch(case![AdminCommand::Untranslated(days)].endpoint(cmd_untranslated))
+        .branch(case![AdminCommand::ReRender(filter)].endpoint(cmd_rerender))
+        .branch(case![AdminCommand::TestRule(gallery)].endpoint(cmd_testrule))
+}
+
+async fn cmd_reload(bot: Bot, msg: Message, config: SharedConfig) -> Result<()> {
+    info!("{}: /reload", msg.from().unwrap().id);
+    let text = match config.reload().await {
+        Ok(fields) if fields.is_empty() => "配置已重新加载".to_string(),
+        Ok(fields) => format!("配置已重新加载，以下字段需要重启才能生效：\n{}", fields.join("\n")),
+        Err(err) => format!("配置重新加载失败：{:#}", err),
+    };
+    reply_to!(bot, msg, escape(&text)).await?;
+    Ok(())
+}
+
+/// /untranslated 最多统计的天数
+const UNTRANSLATED_MAX_DAYS: u32 = 3650;
+
+async fn cmd_untranslated(bot: Bot, msg: Message, trans: EhTagTransDB, days: String) -> Result<()> {
+    info!("{}: /untranslated {}", msg.from().unwrap().id, days);
+    let days = match days.trim() {
+        "" => 30,
+        days => days.parse::<u32>().context("天数应当是一个正整数")?,
+    };
+    let days = days.clamp(1, UNTRANSLATED_MAX_DAYS);
+    let since = Utc::now().date_naive() - chrono::Duration::days(days.into());
+    let galleries = GalleryEntity::list_since(since).await?;
+
+    // 按出现次数从多到少排列
+    let mut counts = IndexMap::<(String, String), usize>::new();
+    for gallery in &galleries {
+        for tag in trans.untranslated(&gallery.tags.0) {
+            *counts.entry(tag).or_default() += 1;
+        }
+    }
+    counts.sort_by(|_, a, _, b| b.cmp(a));
+
+    let mut text = format!("最近 {} 天的 {} 个画廊中，没有翻译的 tag：\n", days, galleries.len());
+    if counts.is_empty() {
+        text.push('无');
+    }
+    for ((namespace, tag), count) in counts {
+        let line = format!("<code>{}:{}</code> × {}\n", escape(&namespace), escape(&tag), count);
+        // Telegram 消息最长 4096 个字符
+        if text.chars().count() + line.chars().count() > 4000 {
+            text.push_str("……");
+            break;
+        }
+        text.push_str(&line);
+    }
+    reply_to!(bot, msg, text).await?;
+    Ok(())
+}
+
+async fn cmd_rerender(
+    bot: Bot,
+    msg: Message,
+    uploader: ExloliUploader,
+    filter: String,
+) -> Result<()> {
+    info!("{}: /rerender {}", msg.from().unwrap().id, filter);
+    let filter = match filter.parse::<RerenderFilter>() {
+        Ok(filter) => filter,
+        Err(err) => {
+            reply_to!(bot, msg, escape(&err)).await?;
+            return Ok(());
+        }
+    };
+    let reply = reply_to!(bot, msg, "开始重新渲染……").await?;
+
+    // 需要修改的消息可能很多，因此在后台运行，并定期更新进度
+    tokio::spawn(async move {
+        let (tx, rx) = watch::channel(RerenderProgress::default());
+        let job = uploader.rerender(&filter, tx);
+        tokio::pin!(job);
+        let mut interval = time::interval(Duration::from_secs(10));
+        let result = loop {
+            tokio::select! {
+                result = &mut job => break result,
+                _ = interval.tick() => {
+                    let text = format!("重新渲染中：{}", *rx.borrow());
+                    // 进度没有变化时修改消息会失败，忽略即可
+                    let _ = bot.edit_message_text(msg.chat.id, reply.id, text).await;
+                }
+            }
+        };
+        let text = match result {
+            Ok(progress) => format!("重新渲染完成：{}", progress),
+            Err(err) => format!("重新渲染失败：{}\n{}", err, *rx.borrow()),
+        };
+        if let Err(err) = bot.edit_message_text(msg.chat.id, reply.id, escape(&text)).await {
+            error!("发送重新渲染结果失败：{}", err);
+        }
+    });
+    Ok(())
 }
 
 // TODO: 该功能需要移除
@@ -48,6 +154,25 @@ async fn cmd_upload(
     Ok(())
 }
 
+async fn cmd_testrule(
+    bot: Bot,
+    msg: Message,
+    uploader: ExloliUploader,
+    gallery: EhGalleryUrl,
+) -> Result<()> {
+    info!("{}: /testrule {}", msg.from().unwrap().id, gallery);
+    let reply = reply_to!(bot, msg, "检查中……").await?;
+    let text = match uploader.test_rules(&gallery).await {
+        Ok((gallery, Ok(()))) => format!("{}\n满足上传规则", gallery.title),
+        Ok((gallery, Err(rejection))) => {
+            format!("{}\n不满足上传规则：{}", gallery.title, rejection)
+        }
+        Err(err) => format!("获取画廊失败：{}", err),
+    };
+    bot.edit_message_text(msg.chat.id, reply.id, escape(&text)).await?;
+    Ok(())
+}
+
 async fn cmd_delete(bot: Bot, msg: Message, command: AdminCommand) -> Result<()> {
     info!("{}: /delete", msg.from().unwrap().id);
     let reply_to = msg.reply_to_message().context("没有回复消息")?;
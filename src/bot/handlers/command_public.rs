@@ -11,16 +11,18 @@ use tracing::info;
 
 use crate::bot::command::{AdminCommand, PublicCommand};
 use crate::bot::handlers::{
-    cmd_best_keyboard, cmd_best_text, cmd_challenge_keyboard, gallery_preview_url,
+    cmd_best_keyboard, cmd_best_text, cmd_challenge_keyboard, cmd_myvotes_text, cmd_random_text,
+    cmd_recommend_text, cmd_search_text, gallery_preview_url,
 };
 use crate::bot::scheduler::Scheduler;
 use crate::bot::utils::{ChallengeLocker, ChallengeProvider};
 use crate::bot::Bot;
 use crate::config::Config;
-use crate::database::{GalleryEntity, MessageEntity, PollEntity};
+use crate::database::{ChannelStats, GalleryEntity, MessageEntity, PollEntity, TagStats};
 use crate::ehentai::{EhGalleryUrl, GalleryInfo};
-use crate::tags::EhTagTransDB;
+use crate::tags::{EhTagTransDB, TagDetail};
 use crate::uploader::ExloliUploader;
+use crate::utils::chart;
 use crate::{reply_to, try_with_reply};
 
 pub fn public_command_handler(
@@ -31,6 +33,12 @@ pub fn public_command_handler(
         .branch(case![PublicCommand::Ping].endpoint(cmd_ping))
         .branch(case![PublicCommand::Update(url)].endpoint(cmd_update))
         .branch(case![PublicCommand::Best(from, to)].endpoint(cmd_best))
+        .branch(case![PublicCommand::Tag(tag)].endpoint(cmd_tag))
+        .branch(case![PublicCommand::Search(query)].endpoint(cmd_search))
+        .branch(case![PublicCommand::Random(args)].endpoint(cmd_random))
+        .branch(case![PublicCommand::Stats(args)].endpoint(cmd_stats))
+        .branch(case![PublicCommand::MyVotes].endpoint(cmd_myvotes))
+        .branch(case![PublicCommand::Recommend].endpoint(cmd_recommend))
         .branch(case![PublicCommand::Challenge].endpoint(cmd_challenge))
         .branch(case![PublicCommand::Upload(gallery)].endpoint(cmd_upload))
         .branch(case![PublicCommand::Help].endpoint(cmd_help))
@@ -72,14 +80,14 @@ async fn cmd_challenge(
     let mut challenge = challange_provider.get_challenge().await.unwrap();
     let answer = challenge[0].clone();
     challenge.shuffle(&mut thread_rng());
-    
+
     // 修复：正确处理完整的 URL 和相对路径
     let url = if answer.url.starts_with("https://") {
         answer.url.clone()
     } else {
         format!("https://telegra.ph{}", answer.url)
     };
-    
+
     let id = locker.add_challenge(answer.id, answer.page, answer.artist.clone());
     let keyboard = cmd_challenge_keyboard(id, &challenge, &trans);
     let reply = bot
@@ -89,8 +97,8 @@ async fn cmd_challenge(
         .reply_to_message_id(msg.id)
         .await?;
     if !msg.chat.is_private() {
-        scheduler.delete_msg(msg.chat.id, msg.id, 120);
-        scheduler.delete_msg(msg.chat.id, reply.id, 120);
+        scheduler.delete_msg(msg.chat.id, msg.id, 120).await;
+        scheduler.delete_msg(msg.chat.id, reply.id, 120).await;
     }
     Ok(())
 }
@@ -108,12 +116,159 @@ async fn cmd_best(
     let reply =
         reply_to!(bot, msg, text).reply_markup(keyboard).disable_web_page_preview(true).await?;
     if !msg.chat.is_private() {
-        scheduler.delete_msg(msg.chat.id, msg.id, 120);
-        scheduler.delete_msg(msg.chat.id, reply.id, 120);
+        scheduler.delete_msg(msg.chat.id, msg.id, 120).await;
+        scheduler.delete_msg(msg.chat.id, reply.id, 120).await;
+    }
+    Ok(())
+}
+
+async fn cmd_search(
+    bot: Bot,
+    msg: Message,
+    cfg: Config,
+    trans: EhTagTransDB,
+    scheduler: Scheduler,
+    query: String,
+) -> Result<()> {
+    info!("{}: /search {}", msg.from().unwrap().id, query);
+    let (text, keyboard) = cmd_search_text(&query, 0, &trans, cfg.telegram.channel_id).await?;
+    let mut reply = reply_to!(bot, msg, text).disable_web_page_preview(true);
+    if let Some(keyboard) = keyboard {
+        reply = reply.reply_markup(keyboard);
+    }
+    let reply = reply.await?;
+    if !msg.chat.is_private() {
+        scheduler.delete_msg(msg.chat.id, msg.id, 120).await;
+        scheduler.delete_msg(msg.chat.id, reply.id, 120).await;
+    }
+    Ok(())
+}
+
+async fn cmd_random(
+    bot: Bot,
+    msg: Message,
+    cfg: Config,
+    trans: EhTagTransDB,
+    scheduler: Scheduler,
+    args: String,
+) -> Result<()> {
+    info!("{}: /random {}", msg.from().unwrap().id, args);
+    let (text, keyboard) = cmd_random_text(&args, &trans, cfg.telegram.channel_id).await?;
+    let mut reply = reply_to!(bot, msg, text);
+    if let Some(keyboard) = keyboard {
+        reply = reply.reply_markup(keyboard);
+    }
+    let reply = reply.await?;
+    if !msg.chat.is_private() {
+        scheduler.delete_msg(msg.chat.id, msg.id, 120).await;
+        scheduler.delete_msg(msg.chat.id, reply.id, 120).await;
+    }
+    Ok(())
+}
+
+async fn cmd_myvotes(bot: Bot, msg: Message, cfg: Config, scheduler: Scheduler) -> Result<()> {
+    let user = msg.from().context("找不到用户")?.id;
+    info!("{}: /myvotes", user);
+    let (text, keyboard) = cmd_myvotes_text(user, 0, cfg.telegram.channel_id).await?;
+    let mut reply = reply_to!(bot, msg, text).disable_web_page_preview(true);
+    if let Some(keyboard) = keyboard {
+        reply = reply.reply_markup(keyboard);
+    }
+    let reply = reply.await?;
+    if !msg.chat.is_private() {
+        scheduler.delete_msg(msg.chat.id, msg.id, 120).await;
+        scheduler.delete_msg(msg.chat.id, reply.id, 120).await;
+    }
+    Ok(())
+}
+
+async fn cmd_recommend(bot: Bot, msg: Message, cfg: Config, scheduler: Scheduler) -> Result<()> {
+    let user = msg.from().context("找不到用户")?.id;
+    info!("{}: /recommend", user);
+    let text = cmd_recommend_text(user, cfg.telegram.channel_id).await?;
+    let reply = reply_to!(bot, msg, text).disable_web_page_preview(true).await?;
+    if !msg.chat.is_private() {
+        scheduler.delete_msg(msg.chat.id, msg.id, 120).await;
+        scheduler.delete_msg(msg.chat.id, reply.id, 120).await;
+    }
+    Ok(())
+}
+
+async fn cmd_stats(
+    bot: Bot,
+    msg: Message,
+    trans: EhTagTransDB,
+    scheduler: Scheduler,
+    args: String,
+) -> Result<()> {
+    info!("{}: /stats {}", msg.from().unwrap().id, args);
+    let stats = ChannelStats::get().await?;
+    let reply = if args.trim() == "chart" {
+        let chart = chart::bar_chart(&stats.scores)?;
+        let caption = format!("分数分布（0 ~ 100 分，每栏 10 分）\n{}", score_distribution(&stats));
+        bot.send_photo(msg.chat.id, InputFile::memory(chart))
+            .caption(caption)
+            .reply_to_message_id(msg.id)
+            .await?
+    } else {
+        reply_to!(bot, msg, stats_text(&stats, &trans).await?).await?
+    };
+    if !msg.chat.is_private() {
+        scheduler.delete_msg(msg.chat.id, msg.id, 120).await;
+        scheduler.delete_msg(msg.chat.id, reply.id, 120).await;
     }
     Ok(())
 }
 
+async fn stats_text(stats: &ChannelStats, trans: &EhTagTransDB) -> Result<String> {
+    let mut text = format!(
+        "<b>画廊</b>：共 {} 本，已删除 {} 本，最近 7 天新增 {} 本，最近 30 天新增 {} 本\n",
+        stats.galleries, stats.deleted, stats.last_week, stats.last_month
+    );
+    text.push_str(&format!(
+        "<b>投票</b>：共 {} 票，{} 人参与，最近 30 天 {} 人参与\n",
+        stats.votes, stats.voters, stats.active_voters
+    ));
+    text.push_str(&format!("<b>分数分布</b>：\n{}\n", score_distribution(stats)));
+
+    for (name, namespaces) in [
+        ("画师", &["artist"][..]),
+        ("原作", &["parody"][..]),
+        ("tag", &["female", "male", "mixed", "other"][..]),
+    ] {
+        let by_count = TagStats::top_by_count(namespaces).await?;
+        let by_score = TagStats::top_by_score(namespaces).await?;
+        text.push_str(&format!("<b>{}（数量）</b>：{}\n", name, tag_stats_text(&by_count, trans)));
+        text.push_str(&format!("<b>{}（均分）</b>：{}\n", name, tag_stats_text(&by_score, trans)));
+    }
+
+    let hosts = stats.hosts.iter().map(|(host, count)| format!("{} {}", escape(host), count));
+    text.push_str(&format!("<b>图床</b>：{}", hosts.collect::<Vec<_>>().join("，")));
+    Ok(text)
+}
+
+/// 每 10 分一栏的分数分布，每行五栏
+fn score_distribution(stats: &ChannelStats) -> String {
+    let buckets = stats
+        .scores
+        .iter()
+        .enumerate()
+        .map(|(i, count)| format!("{}~{}：{}", i * 10, i * 10 + 10, count))
+        .collect::<Vec<_>>();
+    buckets.chunks(5).map(|line| line.join("，")).collect::<Vec<_>>().join("\n")
+}
+
+fn tag_stats_text(tags: &[TagStats], trans: &EhTagTransDB) -> String {
+    if tags.is_empty() {
+        return "无".to_string();
+    }
+    let tags = tags.iter().map(|t| {
+        let name = trans.trans_raw(&t.namespace, &t.tag);
+        format!("{}（{} 本，{:.1}）", escape(&name), t.count, t.score * 100.)
+    });
+    tags.collect::<Vec<_>>().join("，")
+}
+
 async fn cmd_update(bot: Bot, msg: Message, uploader: ExloliUploader, url: String) -> Result<()> {
     info!("{}: /update {}", msg.from().unwrap().id, url);
     let msg_id = if url.is_empty() {
@@ -123,7 +278,7 @@ async fn cmd_update(bot: Bot, msg: Message, uploader: ExloliUploader, url: Strin
     } else {
         Url::parse(&url)?
             .path_segments()
-            .and_then(|p| p.last())
+            .and_then(|mut p| p.next_back())
             .and_then(|id| id.parse::<i32>().ok())
             .ok_or(anyhow!("Invalid URL"))?
     };
@@ -142,12 +297,107 @@ async fn cmd_update(bot: Bot, msg: Message, uploader: ExloliUploader, url: Strin
     Ok(())
 }
 
+async fn cmd_tag(
+    bot: Bot,
+    msg: Message,
+    trans: EhTagTransDB,
+    scheduler: Scheduler,
+    tag: String,
+) -> Result<()> {
+    info!("{}: /tag {}", msg.from().unwrap().id, tag);
+    let text = if !tag.trim().is_empty() {
+        let found = trans.search(&tag);
+        match found.len() {
+            0 => "未找到该 tag".to_string(),
+            1 => tag_detail_text(&found[0]),
+            _ => tag_list_text(&found),
+        }
+    } else if let Some(msg_id) = msg.reply_to_message().and_then(|m| m.forward_from_message_id()) {
+        let msg_entity = MessageEntity::get(msg_id).await?.context("找不到该消息")?;
+        let gallery = GalleryEntity::get(msg_entity.gallery_id).await?.context("找不到该画廊")?;
+        let mut found = vec![];
+        for (namespace, tags) in gallery.tags.0.iter() {
+            for tag in tags {
+                // 没有翻译的 tag 也需要显示出来
+                found.push(trans.detail(namespace, tag).unwrap_or_else(|| TagDetail {
+                    namespace: namespace.clone(),
+                    tag: tag.clone(),
+                    name: String::new(),
+                    intro: String::new(),
+                    links: String::new(),
+                }));
+            }
+        }
+        if found.is_empty() {
+            "该画廊没有 tag".to_string()
+        } else {
+            tag_list_text(&found)
+        }
+    } else {
+        "用法：/tag namespace:tag，或者回复频道中的画廊消息".to_string()
+    };
+
+    let reply = reply_to!(bot, msg, text).disable_web_page_preview(true).await?;
+    if !msg.chat.is_private() {
+        scheduler.delete_msg(msg.chat.id, msg.id, 120).await;
+        scheduler.delete_msg(msg.chat.id, reply.id, 120).await;
+    }
+    Ok(())
+}
+
+/// 单个 tag 的完整介绍
+fn tag_detail_text(tag: &TagDetail) -> String {
+    let mut text = format!("<code>{}:{}</code>", escape(&tag.namespace), escape(&tag.tag));
+    if !tag.name.is_empty() {
+        text.push_str(&format!("\n<b>{}</b>", escape(&tag.name)));
+    }
+    if !tag.intro.is_empty() {
+        text.push_str(&format!("\n\n{}", escape(&truncate(&tag.intro, 3000))));
+    }
+    if !tag.links.is_empty() {
+        text.push_str(&format!("\n\n{}", escape(&truncate(&tag.links, 500))));
+    }
+    text
+}
+
+/// 多个 tag 的列表，每个 tag 一行
+fn tag_list_text(tags: &[TagDetail]) -> String {
+    let mut text = String::new();
+    for tag in tags {
+        let mut line = format!("<code>{}:{}</code>", escape(&tag.namespace), escape(&tag.tag));
+        if !tag.name.is_empty() {
+            line.push_str(&format!(" {}", escape(&tag.name)));
+        }
+        if !tag.intro.is_empty() {
+            line.push_str(&format!("：{}", escape(&truncate(&tag.intro.replace('\n', " "), 40))));
+        }
+        // Telegram 消息最长 4096 个字符
+        if text.chars().count() + line.chars().count() > 4000 {
+            text.push_str("……");
+            break;
+        }
+        text.push_str(&line);
+        text.push('\n');
+    }
+    text
+}
+
+/// 按字符截断文本
+fn truncate(text: &str, len: usize) -> String {
+    let text = text.trim();
+    if text.chars().count() > len {
+        format!("{}……", text.chars().take(len).collect::<String>())
+    } else {
+        text.to_string()
+    }
+}
+
 async fn cmd_ping(bot: Bot, msg: Message, scheduler: Scheduler) -> Result<()> {
     info!("{}: /ping", msg.from().unwrap().id);
     let reply = reply_to!(bot, msg, "pong~").await?;
     if !msg.chat.is_private() {
-        scheduler.delete_msg(msg.chat.id, msg.id, 120);
-        scheduler.delete_msg(msg.chat.id, reply.id, 120);
+        scheduler.delete_msg(msg.chat.id, msg.id, 120).await;
+        scheduler.delete_msg(msg.chat.id, reply.id, 120).await;
     }
     Ok(())
 }
@@ -3,11 +3,12 @@ use teloxide::prelude::*;
 use tracing::info;
 
 use crate::bot::handlers::utils;
+use crate::bot::scheduler::Scheduler;
 use crate::bot::Bot;
 use crate::database::{GalleryEntity, PollEntity};
 use crate::reply_to;
 
-pub async fn custom_pool_sender(bot: Bot, message: Message) -> Result<()> {
+pub async fn custom_pool_sender(bot: Bot, message: Message, scheduler: Scheduler) -> Result<()> {
     info!("频道消息更新，发送投票");
 
     let msg_id = message.forward_from_message_id().context("找不到消息")?;
@@ -40,14 +41,9 @@ pub async fn custom_pool_sender(bot: Bot, message: Message) -> Result<()> {
         .reply_markup(markup)
         .await?;
 
-    tokio::spawn(async move {
-        // 辣鸡 tg 安卓客户端在置顶消息过多时似乎在进群时会卡住
-        // 因此取消置顶频道自动转发的消息
-        tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-        bot.unpin_chat_message(message.chat.id).message_id(message.id).await?;
-        Result::<()>::Ok(())
-    })
-    .await??;
+    // 辣鸡 tg 安卓客户端在置顶消息过多时似乎在进群时会卡住
+    // 因此取消置顶频道自动转发的消息
+    scheduler.unpin_msg(message.chat.id, message.id, 5).await;
 
     Ok(())
 }
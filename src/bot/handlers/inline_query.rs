@@ -0,0 +1,90 @@
+use anyhow::Result;
+use reqwest::Url;
+use teloxide::prelude::*;
+use teloxide::types::{
+    InlineQueryResult, InlineQueryResultArticle, InputMessageContent, InputMessageContentText,
+    ParseMode,
+};
+use teloxide::utils::html::{bold, escape, link};
+use tracing::info;
+
+use super::utils::{gallery_preview_url, parse_search};
+use crate::bot::utils::RateLimiter;
+use crate::bot::Bot;
+use crate::config::Config;
+use crate::database::GalleryEntity;
+use crate::ehentai::GalleryInfo;
+use crate::tags::EhTagTransDB;
+
+/// 每次返回的结果数量，Telegram 最多允许 50 个
+const INLINE_PAGE_SIZE: i32 = 20;
+
+/// 在内联模式中搜索画廊，语法和 /search 相同，通过 offset 翻页
+pub async fn inline_query_handler(
+    bot: Bot,
+    query: InlineQuery,
+    trans: EhTagTransDB,
+    cfg: Config,
+    limiter: RateLimiter,
+) -> Result<()> {
+    let offset = query.offset.parse::<i32>().unwrap_or(0);
+    info!("{}: inline {} {}", query.from.id, offset, query.query);
+    // 输入时每次修改都会触发查询，频率过高时返回空结果，并且不缓存
+    if limiter.insert(query.from.id).is_some() {
+        bot.answer_inline_query(query.id, vec![]).cache_time(0).is_personal(true).await?;
+        return Ok(());
+    }
+
+    // 查询为空或者格式错误时返回空结果，用户可能还在输入
+    let (total, galleries) = match parse_search(&query.query, &trans) {
+        Ok(search) => GalleryEntity::search(&search, INLINE_PAGE_SIZE, offset).await?,
+        Err(_) => (0, vec![]),
+    };
+
+    let mut results = vec![];
+    for (gallery, score) in galleries {
+        let preview = match gallery_preview_url(cfg.telegram.channel_id.clone(), gallery.id).await {
+            Ok(url) => url,
+            Err(_) => gallery.url().url(),
+        };
+        let score = match score {
+            Some(score) => format!("{:.2}", score * 100.),
+            None => "暂无评分".to_string(),
+        };
+        let tags = trans.trans_tags(gallery.tags());
+
+        let mut text = format!("{}\n评分：{}", bold(&escape(&gallery.title_jp())), score);
+        let preview_line = format!("\n{}", link(&preview, "预览"));
+        for (namespace, tags) in &tags {
+            let line = format!("\n{}：{}", escape(namespace), escape(&tags.join(" ")));
+            // Telegram 消息最长 4096 个字符，翻译后的 tag 可能很长
+            let len = text.chars().count() + line.chars().count() + preview_line.chars().count();
+            if len > 4000 {
+                text.push_str("\n……");
+                break;
+            }
+            text.push_str(&line);
+        }
+        text.push_str(&preview_line);
+
+        let description = tags.values().flatten().take(10).cloned().collect::<Vec<_>>();
+        let content = InputMessageContentText::new(text)
+            .parse_mode(ParseMode::Html)
+            .disable_web_page_preview(true);
+        let mut article = InlineQueryResultArticle::new(
+            gallery.id.to_string(),
+            gallery.title_jp(),
+            InputMessageContent::Text(content),
+        )
+        .description(format!("{} | {}", score, description.join(" ")));
+        if let Ok(url) = Url::parse(&preview) {
+            article = article.url(url).hide_url(true);
+        }
+        results.push(InlineQueryResult::Article(article));
+    }
+
+    let next = offset + results.len() as i32;
+    let next_offset = if next < total { next.to_string() } else { String::new() };
+    bot.answer_inline_query(query.id, results).next_offset(next_offset).cache_time(60).await?;
+    Ok(())
+}
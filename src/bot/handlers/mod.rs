@@ -2,6 +2,7 @@ mod callback_query;
 mod command_admin;
 mod command_public;
 mod custom_poll;
+mod inline_query;
 mod join_request;
 mod utils;
 
@@ -9,6 +10,7 @@ pub use callback_query::*;
 pub use command_admin::*;
 pub use command_public::*;
 pub use custom_poll::*;
+pub use inline_query::*;
 pub use join_request::*;
 pub use utils::*;
 
@@ -1,14 +1,18 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use chrono::{Duration, Utc};
 use reqwest::Url;
 use teloxide::prelude::*;
 use teloxide::types::{
     InlineKeyboardButton, InlineKeyboardButtonKind, InlineKeyboardMarkup, MessageId, Recipient,
 };
-use teloxide::utils::html::link;
+use teloxide::utils::html::{escape, link};
 
 use crate::bot::utils::CallbackData;
-use crate::database::{ChallengeView, GalleryEntity, MessageEntity, TelegraphEntity};
+use crate::database::{
+    ChallengeView, GalleryEntity, GallerySearch, MessageEntity, PollEntity, TelegraphEntity,
+    VoteEntity,
+};
+use crate::ehentai::GalleryInfo;
 use crate::tags::EhTagTransDB;
 
 pub fn cmd_challenge_keyboard(
@@ -50,6 +54,198 @@ pub fn cmd_best_keyboard(from: i32, to: i32, offset: i32) -> InlineKeyboardMarku
     ]])
 }
 
+/// 每页显示的搜索结果数量
+const SEARCH_PAGE_SIZE: i32 = 20;
+
+/// 搜索画廊，返回当前页的结果和翻页按钮，page 从 0 开始
+pub async fn cmd_search_text(
+    query: &str,
+    page: i32,
+    trans: &EhTagTransDB,
+    channel: Recipient,
+) -> Result<(String, Option<InlineKeyboardMarkup>)> {
+    let search = match parse_search(query, trans) {
+        Ok(search) => search,
+        Err(err) => return Ok((escape(&err), None)),
+    };
+    let (total, galleries) =
+        GalleryEntity::search(&search, SEARCH_PAGE_SIZE, page * SEARCH_PAGE_SIZE).await?;
+    let pages = (total + SEARCH_PAGE_SIZE - 1) / SEARCH_PAGE_SIZE;
+    if total == 0 {
+        return Ok(("没有找到符合条件的画廊".to_string(), None));
+    }
+
+    let mut text = format!("共找到 {} 个画廊（{}/{}）", total, page + 1, pages);
+    for (gallery, score) in galleries {
+        let url = match gallery_preview_url(channel.clone(), gallery.id).await {
+            Ok(url) => url,
+            Err(_) => gallery.url().url(),
+        };
+        let score = match score {
+            Some(score) => format!("{:.2}", score * 100.),
+            None => "-".to_string(),
+        };
+        let line = format!("\n<code>{}</code> - {}", score, link(&url, &gallery.title));
+        // Telegram 消息最长 4096 个字符
+        if text.chars().count() + line.chars().count() > 4000 {
+            text.push_str("\n……");
+            break;
+        }
+        text.push_str(&line);
+    }
+
+    let mut buttons = vec![];
+    if page > 0 {
+        buttons
+            .push(InlineKeyboardButton::callback("<", CallbackData::SearchPage(page - 1).pack()));
+    }
+    if page + 1 < pages {
+        buttons
+            .push(InlineKeyboardButton::callback(">", CallbackData::SearchPage(page + 1).pack()));
+    }
+    Ok((text, Some(InlineKeyboardMarkup::new(vec![buttons]))))
+}
+
+/// /random 默认的最低分数
+const RANDOM_MIN_SCORE: f32 = 0.8;
+
+/// 随机选择一个符合条件的高分画廊，返回消息内容和“再来一本”按钮
+///
+/// 参数和 /search 相同，最后一个参数为数字时视为最低分数，默认为 80 分
+pub async fn cmd_random_text(
+    args: &str,
+    trans: &EhTagTransDB,
+    channel: Recipient,
+) -> Result<(String, Option<InlineKeyboardMarkup>)> {
+    let args = args.trim();
+    let (filters, last) = args.rsplit_once(' ').unwrap_or(("", args));
+    let (filters, score) = match last.parse::<f32>() {
+        Ok(score) => (filters, Some(score)),
+        Err(_) => (args, None),
+    };
+    let mut search = match filters.trim() {
+        "" => GallerySearch::default(),
+        filters => match parse_search(filters, trans) {
+            Ok(search) => search,
+            Err(err) => return Ok((escape(&err), None)),
+        },
+    };
+    match score {
+        Some(score @ 0.0..=100.0) => search.min_score = Some(score / 100.),
+        Some(score) => return Ok((format!("分数应当在 0 ~ 100 之间：{}", score), None)),
+        None => search.min_score = search.min_score.or(Some(RANDOM_MIN_SCORE)),
+    }
+
+    let (gallery, poll_id) = match GalleryEntity::random(&search).await? {
+        Some(found) => found,
+        None => return Ok(("没有找到符合条件的画廊".to_string(), None)),
+    };
+    let poll = PollEntity::get(poll_id).await?.context("找不到投票")?;
+    let preview = gallery_preview_url(channel, gallery.id).await?;
+    let text = format!(
+        "{}\n评分：{:.2}（前 {:.2}%）\n地址：{}",
+        link(&preview, &gallery.title_jp()),
+        poll.score * 100.,
+        poll.rank().await? * 100.,
+        gallery.url().url(),
+    );
+    let keyboard = InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::callback(
+        "再来一本",
+        CallbackData::Random.pack(),
+    )]]);
+    Ok((text, Some(keyboard)))
+}
+
+/// 每页显示的投票记录数量
+const MY_VOTES_PAGE_SIZE: i32 = 20;
+
+/// 列出用户的投票记录，返回消息内容和翻页按钮
+pub async fn cmd_myvotes_text(
+    user_id: UserId,
+    page: i32,
+    channel: Recipient,
+) -> Result<(String, Option<InlineKeyboardMarkup>)> {
+    let (total, votes) =
+        VoteEntity::list_by_user(user_id.0 as i64, MY_VOTES_PAGE_SIZE, page * MY_VOTES_PAGE_SIZE)
+            .await?;
+    if total == 0 {
+        return Ok(("你还没有给任何画廊投过票".to_string(), None));
+    }
+    let pages = (total + MY_VOTES_PAGE_SIZE - 1) / MY_VOTES_PAGE_SIZE;
+
+    let mut text = format!("共投票 {} 次（{}/{}）", total, page + 1, pages);
+    for vote in votes {
+        let url = match gallery_preview_url(channel.clone(), vote.gallery_id).await {
+            Ok(url) => url,
+            Err(_) => format!("https://exhentai.org/g/{}/{}/", vote.gallery_id, vote.token),
+        };
+        let option =
+            (vote.option as usize).checked_sub(1).and_then(|i| POLL_OPTIONS.get(i)).unwrap_or(&"-");
+        text.push_str(&format!(
+            "\n<code>{}</code> {} - {}",
+            option,
+            vote.vote_time.format("%Y-%m-%d"),
+            link(&url, &vote.title)
+        ));
+    }
+
+    let mut buttons = vec![];
+    if page > 0 {
+        buttons
+            .push(InlineKeyboardButton::callback("<", CallbackData::MyVotesPage(page - 1).pack()));
+    }
+    if page + 1 < pages {
+        buttons
+            .push(InlineKeyboardButton::callback(">", CallbackData::MyVotesPage(page + 1).pack()));
+    }
+    Ok((text, Some(InlineKeyboardMarkup::new(vec![buttons]))))
+}
+
+/// /recommend 推荐的画廊数量
+const RECOMMEND_LIMIT: i32 = 10;
+
+/// 根据用户的投票记录推荐其还没有投过票的画廊
+pub async fn cmd_recommend_text(user_id: UserId, channel: Recipient) -> Result<String> {
+    let galleries = GalleryEntity::recommend(user_id.0 as i64, RECOMMEND_LIMIT).await?;
+    if galleries.is_empty() {
+        return Ok("暂时没有可以推荐的画廊，多给一些画廊投票后再试试吧".to_string());
+    }
+    let mut text = "根据你的投票记录，你可能会喜欢：".to_string();
+    for (gallery, _, score) in galleries {
+        let url = match gallery_preview_url(channel.clone(), gallery.id).await {
+            Ok(url) => url,
+            Err(_) => gallery.url().url(),
+        };
+        text.push_str(&format!(
+            "\n<code>{:.2}</code> - {}",
+            score * 100.,
+            link(&url, &gallery.title)
+        ));
+    }
+    Ok(text)
+}
+
+/// 解析搜索条件，tag 可以使用翻译后的名称，会被转换为 E 站上的原名
+pub fn parse_search(query: &str, trans: &EhTagTransDB) -> Result<GallerySearch, String> {
+    let mut search = query.parse::<GallerySearch>()?;
+    for tag in search.tags.iter_mut().chain(search.exclude.iter_mut()) {
+        *tag = resolve_tag(trans, tag);
+    }
+    Ok(search)
+}
+
+/// 将 namespace:tag 中的翻译后的名称转换为原名，找不到时保持不变
+fn resolve_tag(trans: &EhTagTransDB, tag: &str) -> String {
+    let name = tag.split_once(':').map(|(_, name)| name).unwrap_or(tag);
+    let found = trans.search(tag);
+    match found.first() {
+        Some(first) if !found.iter().any(|d| d.tag == name) => {
+            format!("{}:{}", first.namespace, first.tag)
+        }
+        _ => tag.to_string(),
+    }
+}
+
 pub fn url_of(channel: Recipient, id: i32) -> Url {
     match channel {
         Recipient::Id(chat_id) => Message::url_of(chat_id, None, MessageId(id)).unwrap(),
@@ -59,6 +255,9 @@ pub fn url_of(channel: Recipient, id: i32) -> Url {
     }
 }
 
+/// 投票的选项，第 i 项对应选项 i + 1
+pub const POLL_OPTIONS: [&str; 5] = ["我瞎了", "不咋样", "还行吧", "不错哦", "太棒了"];
+
 pub fn poll_keyboard(poll_id: i64, votes: &[i32; 5]) -> InlineKeyboardMarkup {
     let sum = votes.iter().sum::<i32>();
     let votes: Box<dyn Iterator<Item = f32>> = if sum == 0 {
@@ -67,7 +266,7 @@ pub fn poll_keyboard(poll_id: i64, votes: &[i32; 5]) -> InlineKeyboardMarkup {
         Box::new(votes.iter().map(|&i| i as f32 / sum as f32 * 100.))
     };
 
-    let options = ["我瞎了", "不咋样", "还行吧", "不错哦", "太棒了"]
+    let options = POLL_OPTIONS
         .iter()
         .zip(votes)
         .enumerate()
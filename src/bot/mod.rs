@@ -6,6 +6,7 @@ mod scheduler;
 mod utils;
 
 pub use dispatcher::start_dispatcher;
+pub use scheduler::{Action, Scheduler};
 use teloxide::adaptors::{CacheMe, DefaultParseMode, Throttle};
 
 pub type Bot = CacheMe<DefaultParseMode<Throttle<teloxide::Bot>>>;
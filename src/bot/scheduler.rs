@@ -1,10 +1,30 @@
 use std::time::Duration;
 
+use anyhow::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
 use teloxide::prelude::*;
 use teloxide::types::MessageId;
+use tracing::{debug, error, info, warn};
 
 use crate::bot::Bot;
+use crate::database::ScheduledTaskEntity;
 
+/// 可以延迟执行的消息操作
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Action {
+    /// 删除消息
+    Delete,
+    /// 取消置顶消息
+    Unpin,
+    /// 将消息文本修改为指定内容
+    Edit { text: String },
+}
+
+/// 延迟任务调度器
+///
+/// 任务会先写入数据库再计时，因此重启后可以通过 restore 恢复尚未执行的任务
 #[derive(Debug, Clone)]
 pub struct Scheduler {
     bot: Bot,
@@ -15,11 +35,89 @@ impl Scheduler {
         Self { bot }
     }
 
-    pub fn delete_msg(&self, chat_id: ChatId, msg_id: MessageId, seconds: u64) {
+    /// 恢复数据库中尚未执行的任务，已经过期的任务会立即执行
+    pub async fn restore(&self) -> Result<()> {
+        let tasks = ScheduledTaskEntity::list().await?;
+        info!("恢复计划任务：{} 个", tasks.len());
+        for task in tasks {
+            self.spawn(task);
+        }
+        Ok(())
+    }
+
+    /// 在 delay 之后对指定消息执行操作，返回任务 ID
+    ///
+    /// 执行前删除数据库中对应的记录即可取消任务，见 ScheduledTaskEntity::delete
+    pub async fn schedule(
+        &self,
+        chat_id: ChatId,
+        msg_id: MessageId,
+        action: Action,
+        delay: Duration,
+    ) -> Result<i64> {
+        let run_at = Utc::now().naive_utc() + chrono::Duration::from_std(delay)?;
+        let action = serde_json::to_string(&action)?;
+        let id = ScheduledTaskEntity::create(chat_id.0, msg_id.0, &action, run_at).await?;
+        self.spawn(ScheduledTaskEntity {
+            id,
+            chat_id: chat_id.0,
+            message_id: msg_id.0,
+            action,
+            run_at,
+        });
+        Ok(id)
+    }
+
+    /// 在 seconds 秒后删除消息
+    ///
+    /// 此时消息通常已经发送出去了，因此任务创建失败时只记录日志，不影响调用者
+    pub async fn delete_msg(&self, chat_id: ChatId, msg_id: MessageId, seconds: u64) {
+        let delay = Duration::from_secs(seconds);
+        if let Err(err) = self.schedule(chat_id, msg_id, Action::Delete, delay).await {
+            error!("创建删除消息任务失败：{} {} {}", chat_id, msg_id, err);
+        }
+    }
+
+    /// 在 seconds 秒后取消置顶消息，任务创建失败时只记录日志
+    pub async fn unpin_msg(&self, chat_id: ChatId, msg_id: MessageId, seconds: u64) {
+        let delay = Duration::from_secs(seconds);
+        if let Err(err) = self.schedule(chat_id, msg_id, Action::Unpin, delay).await {
+            error!("创建取消置顶任务失败：{} {} {}", chat_id, msg_id, err);
+        }
+    }
+
+    fn spawn(&self, task: ScheduledTaskEntity) {
         let bot = self.bot.clone();
         tokio::spawn(async move {
-            tokio::time::sleep(Duration::from_secs(seconds)).await;
-            let _ = bot.delete_message(chat_id, msg_id).await;
+            let delay = (task.run_at - Utc::now().naive_utc()).to_std().unwrap_or_default();
+            tokio::time::sleep(delay).await;
+            // 先删除记录，删除失败说明任务已经被取消
+            match ScheduledTaskEntity::delete(task.id).await {
+                Ok(true) => {
+                    if let Err(err) = execute(&bot, &task).await {
+                        warn!("计划任务 {} 执行失败：{}", task.id, err);
+                    }
+                }
+                Ok(false) => debug!("计划任务已取消：{}", task.id),
+                Err(err) => warn!("计划任务 {} 读取失败：{}", task.id, err),
+            }
         });
     }
 }
+
+async fn execute(bot: &Bot, task: &ScheduledTaskEntity) -> Result<()> {
+    let chat_id = ChatId(task.chat_id);
+    let msg_id = MessageId(task.message_id);
+    match serde_json::from_str(&task.action)? {
+        Action::Delete => {
+            bot.delete_message(chat_id, msg_id).await?;
+        }
+        Action::Unpin => {
+            bot.unpin_chat_message(chat_id).message_id(msg_id).await?;
+        }
+        Action::Edit { text } => {
+            bot.edit_message_text(chat_id, msg_id, text).await?;
+        }
+    }
+    Ok(())
+}
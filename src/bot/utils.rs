@@ -34,6 +34,12 @@ pub enum CallbackData {
     PrevPage(i32, i32, i32),
     /// 挑战 ID、画师名称
     Challenge(i64, String),
+    /// 搜索结果的页码，搜索条件从所回复的消息中读取
+    SearchPage(i32),
+    /// 再随机选择一个画廊，筛选条件从所回复的消息中读取
+    Random,
+    /// 投票记录的页码，只有发送 /myvotes 的用户可以翻页
+    MyVotesPage(i32),
 }
 
 impl CallbackData {
@@ -43,11 +49,14 @@ impl CallbackData {
             Self::NextPage(a, b, c) => format!("> {} {} {}", a, b, c),
             Self::PrevPage(a, b, c) => format!("< {} {} {}", a, b, c),
             Self::Challenge(a, b) => format!("challenge {}:{}", a, b),
+            Self::SearchPage(a) => format!("search {}", a),
+            Self::Random => "random".to_string(),
+            Self::MyVotesPage(a) => format!("myvotes {}", a),
         }
     }
 
     pub fn unpack(s: &str) -> Option<Self> {
-        let (cmd, data) = s.split_once(' ')?;
+        let (cmd, data) = s.split_once(' ').unwrap_or((s, ""));
         match cmd {
             "vote" => {
                 let (a, b) = data.split_once(' ')?;
@@ -67,6 +76,9 @@ impl CallbackData {
                 let (a, b) = data.split_once(':')?;
                 Some(Self::Challenge(a.parse().ok()?, b.to_string()))
             }
+            "search" => Some(Self::SearchPage(data.parse().ok()?)),
+            "random" => Some(Self::Random),
+            "myvotes" => Some(Self::MyVotesPage(data.parse().ok()?)),
             _ => None,
         }
     }
@@ -1,13 +1,37 @@
-use std::time::Duration;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use std::{env, fs};
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use chrono::NaiveTime;
 use duration_str::deserialize_duration;
-use once_cell::sync::OnceCell;
-use serde::Deserialize;
+use futures::future::BoxFuture;
+use once_cell::sync::{Lazy, OnceCell};
+use regex::Regex;
+use reqwest::Url;
+use serde::{Deserialize, Deserializer};
 use teloxide::types::{ChatId, Recipient};
+use tokio::time;
+use toml::{Table, Value};
+use tracing::{error, info, warn};
+use tracing_subscriber::EnvFilter;
+
+use crate::ehentai::{ArchiveKind, ArchiveResolution};
+use crate::rules::UploadRules;
+use crate::template;
 
 pub static CHANNEL_ID: OnceCell<String> = OnceCell::new();
 
+/// 环境变量前缀，例如 EXLOLI_TELEGRAM_TOKEN 会覆盖 telegram.token
+const ENV_PREFIX: &str = "EXLOLI_";
+/// 配置文件中的分组，用于将环境变量名拆分为分组和字段
+const SECTIONS: &[&str] = &["exhentai", "telegraph", "telegram", "catbox", "archiver", "rules"];
+/// 可以通过 *_file 从文件中读取的敏感字段，例如 telegram.token_file
+const SECRETS: &[&str] =
+    &["exhentai.cookie", "telegraph.access_token", "telegram.token", "catbox.userhash"];
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     /// 日志等级
@@ -23,6 +47,11 @@ pub struct Config {
     pub telegraph: Telegraph,
     pub telegram: Telegram,
     pub catbox: Catbox,
+    #[serde(default)]
+    pub archiver: Archiver,
+    /// 自动上传前检查画廊的规则
+    #[serde(default)]
+    pub rules: UploadRules,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -35,6 +64,28 @@ pub struct ExHentai {
     pub search_count: usize,
     /// 翻译文件的位置
     pub trans_file: String,
+    /// 本地翻译覆盖文件的位置，用于修改翻译、设置别名和隐藏 tag
+    pub trans_override: Option<String>,
+    /// H@H 下载目录，上传时优先从这里读取原图，为空则全部从 E 站下载
+    pub hath_dir: Option<String>,
+    /// 需要上传的页面较多时，直接下载整个归档，为空则总是逐页下载
+    pub archive_download: Option<ArchiveDownload>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ArchiveDownload {
+    /// 下载原图归档还是重新采样的归档
+    #[serde(default)]
+    pub kind: ArchiveKind,
+    /// 需要上传的页面数量达到多少时才下载归档
+    #[serde(default = "default_min_pages")]
+    pub min_pages: usize,
+    /// 单次下载最多花费的 GP，超出则改为逐页下载，为空则不限制
+    pub max_cost: Option<u64>,
+}
+
+fn default_min_pages() -> usize {
+    50
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -45,11 +96,14 @@ pub struct Telegraph {
     pub author_name: String,
     /// 文章作者连接
     pub author_url: String,
+    /// 文章正文的模板文件，为空则使用默认模板
+    pub article_template: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Telegram {
     /// 频道 id
+    #[serde(deserialize_with = "deserialize_recipient")]
     pub channel_id: Recipient,
     /// bot 名称
     pub bot_id: String,
@@ -59,6 +113,53 @@ pub struct Telegram {
     pub group_id: ChatId,
     /// 入口讨论组 ID
     pub auth_group_id: ChatId,
+    /// 频道消息中 tag 的显示方式
+    #[serde(default)]
+    pub display: TagDisplay,
+    /// 频道消息的模板文件，为空则使用默认模板
+    pub message_template: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TagDisplay {
+    /// 显示翻译后的 tag、原始的英文 tag，或者同时显示两者
+    #[serde(default)]
+    pub language: TagLanguage,
+    /// 需要显示的 namespace 及其顺序，为空则按照画廊中的顺序显示全部
+    #[serde(default)]
+    pub namespaces: Vec<String>,
+    /// 每个 namespace 最多显示的 tag 数量，为空则不限制
+    pub max_tags: Option<usize>,
+    /// 是否将 tag 显示为 hashtag，方便在频道中搜索
+    #[serde(default = "default_true")]
+    pub hashtag: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagLanguage {
+    /// 翻译后的中文名称
+    #[default]
+    Translated,
+    /// E 站上的英文原名
+    Original,
+    /// 中文名称后面用括号注明英文原名
+    Both,
+}
+
+impl Default for TagDisplay {
+    fn default() -> Self {
+        Self {
+            language: TagLanguage::Translated,
+            namespaces: vec![],
+            max_tags: None,
+            hashtag: true,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -67,9 +168,527 @@ pub struct Catbox {
     pub api_url: String,
 }
 
+/// exloli-archiver 的配置
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Archiver {
+    /// 守护模式下重新遍历收藏夹的间隔
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub poll_interval: Duration,
+    /// 每次成功请求归档后的等待时间
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub delay: Duration,
+    /// 指定时间段内使用不同的等待时间
+    pub windows: Vec<ScheduleWindow>,
+    /// 每天最多成功请求归档的次数，为空则不限制
+    pub daily_limit: Option<u32>,
+    /// 默认请求的分辨率
+    pub resolution: ArchiveResolution,
+    /// 为指定的收藏分类使用不同的分辨率，键为收藏分类编号
+    pub favcats: HashMap<String, ArchiveResolution>,
+    /// 单次请求最多花费的 GP，超出则不请求，为空则不限制
+    pub max_cost: Option<u64>,
+}
+
+/// 一个时间段，可以跨越零点，例如 22:00 ~ 02:00
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ScheduleWindow {
+    /// 开始时间，格式为 HH:MM
+    #[serde(deserialize_with = "deserialize_time")]
+    pub start: NaiveTime,
+    /// 结束时间，格式为 HH:MM，不包含在时间段内
+    #[serde(deserialize_with = "deserialize_time")]
+    pub end: NaiveTime,
+    /// 该时间段内每次成功请求归档后的等待时间
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub delay: Duration,
+}
+
+impl Default for Archiver {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(60 * 60),
+            delay: Duration::from_secs(60 * 60),
+            // 凌晨 E 站比较空闲，可以请求得快一些
+            windows: vec![ScheduleWindow {
+                start: NaiveTime::from_hms_opt(2, 0, 0).unwrap(),
+                end: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+                delay: Duration::from_secs(20 * 60),
+            }],
+            daily_limit: None,
+            resolution: ArchiveResolution::Original,
+            favcats: HashMap::new(),
+            max_cost: None,
+        }
+    }
+}
+
+impl Archiver {
+    /// 获取在指定时间成功请求归档后需要等待的时间
+    pub fn delay_at(&self, time: NaiveTime) -> Duration {
+        self.windows.iter().find(|w| w.contains(time)).map(|w| w.delay).unwrap_or(self.delay)
+    }
+
+    /// 获取指定收藏分类请求归档时使用的分辨率
+    pub fn resolution_of(&self, favcat: u32) -> ArchiveResolution {
+        self.favcats.get(&favcat.to_string()).copied().unwrap_or(self.resolution)
+    }
+}
+
+impl ScheduleWindow {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
 impl Config {
+    /// 读取配置文件，依次应用环境变量覆盖、读取 *_file 指定的敏感字段，最后检查配置是否合法
+    pub fn new(path: &str) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("无法读取配置文件：{path}"))?;
+        let config =
+            Self::parse(&text, env::vars()).with_context(|| format!("配置文件解析失败：{path}"))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn parse(text: &str, vars: impl IntoIterator<Item = (String, String)>) -> Result<Self> {
+        let vars = vars.into_iter().collect::<Vec<_>>();
+        let table = text.parse::<Table>()?;
+        match Self::from_table(table.clone(), &vars, false) {
+            Ok(config) => Ok(config),
+            // 配置文件中没有的字段无法确定类型，例如纯数字的 cookie 会被解析为整数，
+            // 因此失败时将这些环境变量视为字符串再试一次
+            Err(err) => Self::from_table(table, &vars, true).map_err(|_| err),
+        }
+    }
+
+    fn from_table(mut table: Table, vars: &[(String, String)], as_string: bool) -> Result<Self> {
+        apply_env_overrides(&mut table, vars.iter().cloned(), as_string);
+        read_secret_files(&mut table)?;
+        Ok(Value::Table(table).try_into()?)
+    }
+
+    /// 检查配置是否合法，会一次性列出所有问题
+    pub fn validate(&self) -> Result<()> {
+        static TOKEN_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\d+:[\w-]+$").unwrap());
+
+        let mut errors = vec![];
+        if let Err(err) = EnvFilter::try_new(&self.log_level) {
+            errors.push(format!("log_level 无法解析：{err}"));
+        }
+        if self.threads_num == 0 {
+            errors.push("threads_num 至少为 1".to_string());
+        }
+        if self.interval.is_zero() {
+            errors.push("interval 不能为 0，例如可以填写 \"1h\"".to_string());
+        }
+        if self.database_url.is_empty() {
+            errors.push("database_url 不能为空，例如可以填写 \"db.sqlite\"".to_string());
+        }
+
+        let cookie = &self.exhentai.cookie;
+        if !cookie.contains("ipb_member_id") || !cookie.contains("ipb_pass_hash") {
+            errors.push(
+                "exhentai.cookie 缺少 ipb_member_id 或 ipb_pass_hash，请从已登录的浏览器中复制完整的 cookie"
+                    .to_string(),
+            );
+        }
+        if self.exhentai.search_params.is_empty() {
+            errors.push(
+                "exhentai.search_params 不能为空，例如 [[\"f_search\", \"language:chinese\"]]"
+                    .to_string(),
+            );
+        }
+        for (key, _) in &self.exhentai.search_params {
+            if key.is_empty() {
+                errors.push("exhentai.search_params 中存在空的参数名".to_string());
+            }
+        }
+
+        if self.telegraph.access_token.is_empty() {
+            errors.push(
+                "telegraph.access_token 不能为空，可以通过 https://api.telegra.ph/createAccount 获取"
+                    .to_string(),
+            );
+        }
+        if let Err(err) = Url::parse(&self.telegraph.author_url) {
+            errors.push(format!("telegraph.author_url 不是有效的 URL：{err}"));
+        }
+
+        match &self.telegram.channel_id {
+            Recipient::ChannelUsername(name) if !name.starts_with('@') => errors
+                .push(format!("telegram.channel_id 为频道用户名时需要以 @ 开头，例如 \"@{name}\"")),
+            Recipient::Id(id) if id.0 >= 0 => errors.push(format!(
+                "telegram.channel_id 不是有效的频道 ID：{id}，频道 ID 应当是以 -100 开头的负数"
+            )),
+            _ => (),
+        }
+        for (name, id) in
+            [("group_id", self.telegram.group_id), ("auth_group_id", self.telegram.auth_group_id)]
+        {
+            if id.0 >= 0 {
+                errors.push(format!(
+                    "telegram.{name} 不是有效的群组 ID：{id}，群组 ID 应当是负数，可以通过 @myidbot 获取"
+                ));
+            }
+        }
+        if !TOKEN_RE.is_match(&self.telegram.token) {
+            errors.push(
+                "telegram.token 格式不正确，应当形如 123456:ABC-DEF，可以从 @BotFather 获取"
+                    .to_string(),
+            );
+        }
+        if self.telegram.bot_id.is_empty() {
+            errors.push("telegram.bot_id 不能为空".to_string());
+        }
+        if self.telegram.display.max_tags == Some(0) {
+            errors.push(
+                "telegram.display.max_tags 不能为 0，如果不需要限制，请删除该字段".to_string(),
+            );
+        }
+
+        if let Some(file) = &self.exhentai.trans_override {
+            if !Path::new(file).is_file() {
+                errors.push(format!("exhentai.trans_override 不是一个文件：{file}"));
+            }
+        }
+        if let Some(dir) = &self.exhentai.hath_dir {
+            if !Path::new(dir).is_dir() {
+                errors.push(format!("exhentai.hath_dir 不是一个目录：{dir}"));
+            }
+        }
+
+        for (name, file) in [
+            ("telegram.message_template", &self.telegram.message_template),
+            ("telegraph.article_template", &self.telegraph.article_template),
+        ] {
+            if let Some(Err(err)) = file.as_deref().map(template::check) {
+                errors.push(format!("{name} 无法使用：{err:#}"));
+            }
+        }
+
+        if let Err(err) = Url::parse(&self.catbox.api_url) {
+            errors.push(format!("catbox.api_url 不是有效的 URL：{err}"));
+        }
+
+        if self.archiver.poll_interval.is_zero() {
+            errors.push("archiver.poll_interval 不能为 0".to_string());
+        }
+        if self.archiver.daily_limit == Some(0) {
+            errors.push("archiver.daily_limit 不能为 0，如果不需要限制，请删除该字段".to_string());
+        }
+        for favcat in self.archiver.favcats.keys() {
+            if !matches!(favcat.parse::<u32>(), Ok(0..=9)) {
+                errors.push(format!("archiver.favcats 中的收藏分类 {favcat} 应该为 0 ~ 9"));
+            }
+        }
+        for window in &self.archiver.windows {
+            if window.start == window.end {
+                errors.push(format!(
+                    "archiver.windows 中的时间段 {} ~ {} 开始时间和结束时间不能相同",
+                    window.start, window.end
+                ));
+            }
+        }
+
+        errors.extend(self.rules.validate());
+
+        if !errors.is_empty() {
+            bail!("配置检查未通过：\n{}", errors.join("\n"));
+        }
+        Ok(())
+    }
+
+    /// 将无法热更新的字段恢复为旧配置中的值，返回发生了变化的字段名
+    fn keep_cold_fields(&mut self, old: &Config) -> Vec<&'static str> {
+        let mut fields = vec![];
+        macro_rules! keep {
+            ($($head:ident $(.$tail:ident)*),* $(,)?) => {$(
+                if self.$head$(.$tail)* != old.$head$(.$tail)* {
+                    self.$head$(.$tail)* = old.$head$(.$tail)*.clone();
+                    fields.push(concat!(stringify!($head) $(, ".", stringify!($tail))*));
+                }
+            )*};
+        }
+        keep!(
+            log_level,
+            threads_num,
+            database_url,
+            exhentai.cookie,
+            exhentai.trans_file,
+            exhentai.trans_override,
+            telegraph.access_token,
+            telegraph.author_name,
+            telegraph.author_url,
+            telegram.channel_id,
+            telegram.bot_id,
+            telegram.token,
+        );
+        fields
+    }
+}
+
+/// 重新加载配置之后执行的回调，用于通知依赖配置的组件
+pub type ReloadHook = Arc<dyn Fn() -> BoxFuture<'static, Result<()>> + Send + Sync>;
+
+/// 可以在运行时重新加载的配置
+///
+/// 各个组件持有同一份 SharedConfig，每次使用时通过 get 获取当前配置的快照
+#[derive(Clone)]
+pub struct SharedConfig {
+    path: String,
+    inner: Arc<RwLock<Config>>,
+    hook: Option<ReloadHook>,
+}
+
+impl std::fmt::Debug for SharedConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedConfig")
+            .field("path", &self.path)
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SharedConfig {
     pub fn new(path: &str) -> Result<Self> {
-        let s = std::fs::read_to_string(path)?;
-        Ok(toml::from_str(&s)?)
+        let config = Config::new(path)?;
+        Ok(Self { path: path.to_string(), inner: Arc::new(RwLock::new(config)), hook: None })
+    }
+
+    /// 设置重新加载配置之后执行的回调，自动重新加载和 /reload 都会执行
+    ///
+    /// 需要在克隆之前设置
+    pub fn with_reload_hook(mut self, hook: ReloadHook) -> Self {
+        self.hook = Some(hook);
+        self
+    }
+
+    /// 获取当前配置
+    pub fn get(&self) -> Config {
+        self.inner.read().unwrap().clone()
+    }
+
+    /// 重新读取配置文件，返回需要重启才能生效的字段
+    ///
+    /// 如果新的配置无法通过检查，则继续使用旧配置，也不会执行回调
+    pub async fn reload(&self) -> Result<Vec<&'static str>> {
+        let mut config = Config::new(&self.path)?;
+        let fields = {
+            let mut lock = self.inner.write().unwrap();
+            let fields = config.keep_cold_fields(&lock);
+            *lock = config;
+            fields
+        };
+        if let Some(hook) = &self.hook {
+            hook().await.context("配置已经生效，但是执行重新加载后的处理失败")?;
+        }
+        Ok(fields)
+    }
+
+    /// 监听配置文件的变化，并自动重新加载
+    pub async fn watch(&self) {
+        let mut last = modified_time(&self.path);
+        loop {
+            time::sleep(Duration::from_secs(10)).await;
+            let current = modified_time(&self.path);
+            if current == last {
+                continue;
+            }
+            last = current;
+            info!("检测到配置文件变化，重新加载");
+            match self.reload().await {
+                Ok(fields) if fields.is_empty() => info!("配置重新加载完毕"),
+                Ok(fields) => warn!("配置重新加载完毕，以下字段需要重启才能生效：{:?}", fields),
+                Err(err) => error!("配置重新加载失败：{:#}", err),
+            }
+        }
+    }
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// 反序列化频道 ID，字符串形式的数字 ID 也会被视为数字 ID
+fn deserialize_recipient<'de, D: Deserializer<'de>>(d: D) -> Result<Recipient, D::Error> {
+    Ok(match Recipient::deserialize(d)? {
+        Recipient::ChannelUsername(name) => match name.parse() {
+            Ok(id) => Recipient::Id(ChatId(id)),
+            Err(_) => Recipient::ChannelUsername(name),
+        },
+        id => id,
+    })
+}
+
+/// 反序列化 HH:MM 格式的时间
+fn deserialize_time<'de, D: Deserializer<'de>>(d: D) -> Result<NaiveTime, D::Error> {
+    let s = String::deserialize(d)?;
+    NaiveTime::parse_from_str(&s, "%H:%M").map_err(serde::de::Error::custom)
+}
+
+/// 使用 EXLOLI_ 开头的环境变量覆盖配置文件中的值
+///
+/// 环境变量的值会先尝试按 TOML 解析，以支持数字和数组，如果失败或者原值是字符串，则视为字符串，
+/// as_string 为 true 时，配置文件中不存在的字段也视为字符串
+fn apply_env_overrides(
+    table: &mut Table,
+    vars: impl IntoIterator<Item = (String, String)>,
+    as_string: bool,
+) {
+    for (key, value) in vars {
+        let Some(key) = key.strip_prefix(ENV_PREFIX) else { continue };
+        let key = key.to_lowercase();
+        let section = SECTIONS.iter().find_map(|&section| {
+            key.strip_prefix(section)?.strip_prefix('_').map(|field| (section, field))
+        });
+        let (target, field) = match section {
+            Some((section, field)) => {
+                let target = table.entry(section).or_insert_with(|| Value::Table(Table::new()));
+                match target.as_table_mut() {
+                    Some(target) => (target, field),
+                    None => continue,
+                }
+            }
+            None => (&mut *table, key.as_str()),
+        };
+        let value = match target.get(field) {
+            Some(Value::String(_)) => Value::String(value),
+            None if as_string => Value::String(value),
+            _ => parse_env_value(&value),
+        };
+        target.insert(field.to_string(), value);
+    }
+}
+
+fn parse_env_value(value: &str) -> Value {
+    format!("value = {value}")
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(value.to_string()))
+}
+
+/// 读取 *_file 指定的文件作为对应字段的值，适用于 docker secrets
+fn read_secret_files(table: &mut Table) -> Result<()> {
+    for secret in SECRETS {
+        let (section, field) = secret.split_once('.').unwrap();
+        let Some(section) = table.get_mut(section).and_then(Value::as_table_mut) else { continue };
+        let Some(path) = section.remove(&format!("{field}_file")) else { continue };
+        let path = path.as_str().with_context(|| format!("{secret}_file 应当是一个文件路径"))?;
+        let value = fs::read_to_string(path)
+            .with_context(|| format!("无法读取 {secret}_file 指定的文件：{path}"))?;
+        section.insert(field.to_string(), Value::String(value.trim_end().to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        log_level = "info"
+        threads_num = 1
+        interval = "1h"
+        database_url = "db.sqlite"
+
+        [exhentai]
+        cookie = "ipb_member_id=1; ipb_pass_hash=2"
+        search_params = [["f_search", "language:chinese"]]
+        search_count = 10
+        trans_file = "db.text.json"
+
+        [telegraph]
+        access_token = "token"
+        author_name = "exloli"
+        author_url = "https://t.me/exlolicon"
+
+        [telegram]
+        channel_id = "@exlolicon"
+        group_id = -1001423106182
+        auth_group_id = -1001423106182
+        bot_id = "test_bot"
+        token = "123456:ABC-DEF"
+
+        [catbox]
+        userhash = "123456"
+        api_url = "https://catbox.moe/user/api.php"
+    "#;
+
+    #[test]
+    fn env_overrides() {
+        let vars = [
+            ("EXLOLI_INTERVAL", "2h"),
+            ("EXLOLI_TELEGRAM_CHANNEL_ID", "-1001234567890"),
+            ("EXLOLI_EXHENTAI_SEARCH_COUNT", "20"),
+            ("EXLOLI_CATBOX_USERHASH", "654321"),
+        ];
+        let vars = vars.map(|(k, v)| (k.to_string(), v.to_string()));
+        let config = Config::parse(CONFIG, vars).unwrap();
+        assert_eq!(config.interval, Duration::from_secs(7200));
+        assert_eq!(config.telegram.channel_id, Recipient::Id(ChatId(-1001234567890)));
+        assert_eq!(config.exhentai.search_count, 20);
+        assert_eq!(config.catbox.userhash, "654321");
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn env_override_missing_string() {
+        let text = CONFIG.replace(r#"cookie = "ipb_member_id=1; ipb_pass_hash=2""#, "");
+        let vars = [("EXLOLI_EXHENTAI_COOKIE", "123"), ("EXLOLI_EXHENTAI_SEARCH_COUNT", "20")];
+        let vars = vars.map(|(k, v)| (k.to_string(), v.to_string()));
+        let config = Config::parse(&text, vars).unwrap();
+        assert_eq!(config.exhentai.cookie, "123");
+        assert_eq!(config.exhentai.search_count, 20);
+    }
+
+    #[test]
+    fn archiver_delay() {
+        let text = format!(
+            "{CONFIG}{}",
+            r#"
+            [archiver]
+            delay = "1h"
+            windows = [{ start = "22:00", end = "02:00", delay = "10m" }]
+            "#
+        );
+        let config = Config::parse(&text, []).unwrap();
+        let time = |h| NaiveTime::from_hms_opt(h, 0, 0).unwrap();
+        assert_eq!(config.archiver.delay_at(time(23)), Duration::from_secs(600));
+        assert_eq!(config.archiver.delay_at(time(1)), Duration::from_secs(600));
+        assert_eq!(config.archiver.delay_at(time(2)), Duration::from_secs(3600));
+        assert_eq!(config.archiver.poll_interval, Archiver::default().poll_interval);
+    }
+
+    #[test]
+    fn tag_display() {
+        let config = Config::parse(CONFIG, []).unwrap();
+        assert_eq!(config.telegram.display, TagDisplay::default());
+
+        let text = format!(
+            "{CONFIG}{}",
+            r#"
+            [telegram.display]
+            language = "both"
+            namespaces = ["artist", "female"]
+            "#
+        );
+        let config = Config::parse(&text, []).unwrap();
+        assert_eq!(config.telegram.display.language, TagLanguage::Both);
+        assert_eq!(config.telegram.display.namespaces, ["artist", "female"]);
+        assert!(config.telegram.display.hashtag);
+    }
+
+    #[test]
+    fn validate_channel_id() {
+        let vars = [("EXLOLI_TELEGRAM_CHANNEL_ID".to_string(), "exlolicon".to_string())];
+        let config = Config::parse(CONFIG, vars).unwrap();
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("\"@exlolicon\""), "{err}");
     }
 }
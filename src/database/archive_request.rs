@@ -0,0 +1,123 @@
+use chrono::{NaiveDateTime, Utc};
+use sqlx::Result;
+use tracing::Level;
+
+use super::db::DB;
+
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct ArchiveRequestEntity {
+    /// 记录 ID
+    pub id: i64,
+    /// 画廊 ID
+    pub gallery_id: i32,
+    /// 画廊 token
+    pub token: String,
+    /// 请求时间
+    pub requested_at: NaiveDateTime,
+    /// 请求的分辨率，见 ArchiveResolution::as_str
+    pub resolution: String,
+    /// 是否请求成功
+    pub success: bool,
+    /// 附加信息，失败时为错误原因
+    pub message: Option<String>,
+    /// 花费的 GP，未知时为空
+    pub cost: Option<i64>,
+}
+
+/// 归档请求的统计信息
+#[derive(Debug)]
+pub struct ArchiveStats {
+    /// 成功请求的画廊数量
+    pub success: i32,
+    /// 最近一次请求失败，且从未成功过的画廊数量
+    pub failed: i32,
+    /// 累计花费的 GP
+    pub cost: i64,
+    /// 最近 24 小时内的请求次数
+    pub recent: i32,
+}
+
+impl ArchiveRequestEntity {
+    /// 记录一次归档请求
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn create(
+        gallery_id: i32,
+        token: &str,
+        resolution: &str,
+        success: bool,
+        message: Option<&str>,
+        cost: Option<i64>,
+    ) -> Result<i64> {
+        let now = Utc::now().naive_utc();
+        sqlx::query!(
+            "INSERT INTO archive_request (gallery_id, token, requested_at, resolution, success, message, cost) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            gallery_id,
+            token,
+            now,
+            resolution,
+            success,
+            message,
+            cost,
+        )
+        .execute(&*DB)
+        .await
+        .map(|r| r.last_insert_rowid())
+    }
+
+    /// 检查画廊是否已经请求过归档，无论成功与否，失败的画廊需要通过 retry-failed 重新请求
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn is_requested(gallery_id: i32) -> Result<bool> {
+        sqlx::query_scalar!(
+            "SELECT EXISTS(SELECT 1 FROM archive_request WHERE gallery_id = ?)",
+            gallery_id
+        )
+        .fetch_one(&*DB)
+        .await
+        .map(|x| x == Some(1))
+    }
+
+    /// 统计指定时间（UTC）之后成功的请求次数
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn count_since(since: NaiveDateTime) -> Result<i32> {
+        sqlx::query_scalar!(
+            "SELECT COUNT(*) FROM archive_request WHERE requested_at >= ? AND success = TRUE",
+            since
+        )
+        .fetch_one(&*DB)
+        .await
+    }
+
+    /// 列出从未成功过的画廊的最近一次失败记录，按请求时间排列
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn list_failed() -> Result<Vec<Self>> {
+        sqlx::query_as(
+            r#"SELECT * FROM archive_request
+            WHERE id IN (SELECT MAX(id) FROM archive_request GROUP BY gallery_id HAVING MAX(success) = FALSE)
+            ORDER BY requested_at"#,
+        )
+        .fetch_all(&*DB)
+        .await
+    }
+
+    /// 统计归档请求的情况
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn stats() -> Result<ArchiveStats> {
+        let since = Utc::now().naive_utc() - chrono::Duration::days(1);
+        let record = sqlx::query!(
+            r#"SELECT
+                (SELECT COUNT(DISTINCT gallery_id) FROM archive_request WHERE success = TRUE) as "success!: i32",
+                (SELECT COUNT(*) FROM (SELECT gallery_id FROM archive_request GROUP BY gallery_id HAVING MAX(success) = FALSE)) as "failed!: i32",
+                (SELECT COALESCE(SUM(cost), 0) FROM archive_request) as "cost!: i64",
+                (SELECT COUNT(*) FROM archive_request WHERE requested_at >= ?) as "recent!: i32""#,
+            since,
+        )
+        .fetch_one(&*DB)
+        .await?;
+        Ok(ArchiveStats {
+            success: record.success,
+            failed: record.failed,
+            cost: record.cost,
+            recent: record.recent,
+        })
+    }
+}
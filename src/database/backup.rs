@@ -0,0 +1,432 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use chrono::NaiveDate;
+use futures::TryStreamExt;
+use serde_json::{Map, Number, Value};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Column, Row, Sqlite, Transaction, TypeInfo, ValueRef};
+use tracing::{debug, info};
+
+use super::db::DB;
+use super::gallery::GalleryEntity;
+
+/// CSV 中表示 NULL 的值，用于和空字符串区分
+const CSV_NULL: &str = "\\N";
+
+/// 导出时，在指定时间范围内发布的画廊
+const GALLERIES: &str =
+    "SELECT gallery_id FROM message WHERE publish_date >= ?1 AND publish_date < ?2";
+
+/// 需要导出的表，按照导入时的顺序排列
+const TABLES: &[Table] = &[
+    Table { name: "gallery", filter: "id IN (GALLERIES)", dedup: None },
+    Table { name: "message", filter: "publish_date >= ?1 AND publish_date < ?2", dedup: None },
+    Table { name: "telegraph", filter: "gallery_id IN (GALLERIES)", dedup: None },
+    Table {
+        name: "image",
+        filter: "id IN (SELECT image_id FROM page WHERE gallery_id IN (GALLERIES))",
+        dedup: None,
+    },
+    Table { name: "page", filter: "gallery_id IN (GALLERIES)", dedup: None },
+    Table { name: "poll", filter: "gallery_id IN (GALLERIES)", dedup: None },
+    Table {
+        name: "vote",
+        filter: "poll_id IN (SELECT id FROM poll WHERE gallery_id IN (GALLERIES))",
+        dedup: None,
+    },
+    Table {
+        name: "challenge_history",
+        filter: "answer_time >= ?1 AND answer_time < ?2",
+        dedup: Some(&["user_id", "gallery_id", "page", "answer_time"]),
+    },
+];
+
+struct Table {
+    name: &'static str,
+    /// 按时间范围导出时使用的条件，GALLERIES 会被替换为范围内的画廊
+    filter: &'static str,
+    /// 使用自增主键的表，导入时不保留主键，而是根据这些列判断记录是否已经存在
+    dedup: Option<&'static [&'static str]>,
+}
+
+/// 导出文件的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackupFormat {
+    /// 每行一个 JSON 对象
+    #[default]
+    Jsonl,
+    /// 带表头的 CSV，NULL 写作 \N
+    Csv,
+}
+
+impl BackupFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Jsonl => "jsonl",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for BackupFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "jsonl" | "json" => Ok(Self::Jsonl),
+            "csv" => Ok(Self::Csv),
+            _ => Err(format!("不支持的格式：{s}，可选 jsonl、csv")),
+        }
+    }
+}
+
+impl Display for BackupFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+/// 将数据库导出到指定目录，每个表一个文件，返回每个表导出的记录数
+///
+/// 指定时间范围时，只导出在此期间发布的画廊及其页面、图片、投票，以及此期间的验证记录
+pub async fn export(
+    dir: &Path,
+    format: BackupFormat,
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+) -> Result<Vec<(&'static str, usize)>> {
+    std::fs::create_dir_all(dir)?;
+    let filtered = since.is_some() || until.is_some();
+    // NOTE: 日期在 sqlite 中以字符串比较，NaiveDate::MAX 会被格式化为 +262142-12-31，不能用作上限
+    let since = since.unwrap_or_default().to_string();
+    // 结束日期包含在范围内，因此比较时使用下一天
+    let until = until.and_then(|d| d.succ_opt()).map(|d| d.to_string());
+    let until = until.unwrap_or_else(|| "9999-12-31".to_string());
+
+    let mut counts = vec![];
+    for table in TABLES {
+        let sql = if filtered {
+            let filter = table.filter.replace("GALLERIES", GALLERIES);
+            format!("SELECT * FROM {} WHERE {}", table.name, filter)
+        } else {
+            format!("SELECT * FROM {}", table.name)
+        };
+        debug!("{}", sql);
+        let mut query = sqlx::query(&sql);
+        if filtered {
+            query = query.bind(&since).bind(&until);
+        }
+        let mut rows = query.fetch(&*DB);
+
+        let path = dir.join(format!("{}.{}", table.name, format.extension()));
+        let mut writer = Writer::new(&path, format)?;
+        while let Some(row) = rows.try_next().await? {
+            writer.write(&row_to_json(&row))?;
+        }
+        let count = writer.finish()?;
+        info!("已导出 {}：{} 条", table.name, count);
+        counts.push((table.name, count));
+    }
+    Ok(counts)
+}
+
+/// 从指定目录导入数据，可以合并到已有的数据库中，返回每个表新增的记录数
+///
+/// 已经存在的记录会被跳过，因此重复导入同一份数据不会产生影响
+pub async fn import(dir: &Path) -> Result<Vec<(&'static str, u64)>> {
+    let mut tx = DB.begin().await?;
+    let mut counts = vec![];
+    for table in TABLES {
+        let rows = match read_rows(dir, table.name)? {
+            Some(rows) => rows,
+            None => continue,
+        };
+        let columns = table_columns(&mut tx, table.name).await?;
+        let mut inserted = 0;
+        for row in rows {
+            inserted += insert_row(&mut tx, table, &columns, row?).await?;
+        }
+        info!("已导入 {}：{} 条", table.name, inserted);
+        counts.push((table.name, inserted));
+    }
+    tx.commit().await?;
+    // 导入的画廊是直接写入 gallery 表的，需要重建全文索引
+    GalleryEntity::rebuild_index().await?;
+    Ok(counts)
+}
+
+fn row_to_json(row: &SqliteRow) -> Map<String, Value> {
+    let mut map = Map::new();
+    for (i, column) in row.columns().iter().enumerate() {
+        // NOTE: sqlite 的类型是动态的，需要根据每个值实际的存储类型来读取
+        let value = match row.try_get_raw(i) {
+            Ok(raw) if raw.is_null() => Value::Null,
+            Ok(raw) => match raw.type_info().name() {
+                "INTEGER" => row.try_get::<i64, _>(i).map(Value::from).unwrap_or_default(),
+                "REAL" => row.try_get::<f64, _>(i).map(Value::from).unwrap_or_default(),
+                _ => row.try_get::<String, _>(i).map(Value::from).unwrap_or_default(),
+            },
+            Err(_) => Value::Null,
+        };
+        map.insert(column.name().to_string(), value);
+    }
+    map
+}
+
+enum Writer {
+    Jsonl(BufWriter<File>, usize),
+    Csv(Box<csv::Writer<File>>, Option<Vec<String>>, usize),
+}
+
+impl Writer {
+    fn new(path: &Path, format: BackupFormat) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("无法创建 {}", path.display()))?;
+        Ok(match format {
+            BackupFormat::Jsonl => Self::Jsonl(BufWriter::new(file), 0),
+            BackupFormat::Csv => Self::Csv(Box::new(csv::Writer::from_writer(file)), None, 0),
+        })
+    }
+
+    fn write(&mut self, row: &Map<String, Value>) -> Result<()> {
+        match self {
+            Self::Jsonl(writer, count) => {
+                serde_json::to_writer(&mut *writer, row)?;
+                writer.write_all(b"\n")?;
+                *count += 1;
+            }
+            Self::Csv(writer, header, count) => {
+                if header.is_none() {
+                    let keys = row.keys().cloned().collect::<Vec<_>>();
+                    writer.write_record(&keys)?;
+                    *header = Some(keys);
+                }
+                writer.write_record(row.values().map(|v| match v {
+                    Value::Null => CSV_NULL.to_string(),
+                    Value::String(s) => s.clone(),
+                    v => v.to_string(),
+                }))?;
+                *count += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<usize> {
+        match self {
+            Self::Jsonl(mut writer, count) => {
+                writer.flush()?;
+                Ok(count)
+            }
+            Self::Csv(mut writer, _, count) => {
+                writer.flush()?;
+                Ok(count)
+            }
+        }
+    }
+}
+
+type Rows = Box<dyn Iterator<Item = Result<Map<String, Value>>>>;
+
+/// 读取某个表的导出文件，如果文件不存在则返回 None
+fn read_rows(dir: &Path, table: &str) -> Result<Option<Rows>> {
+    let path = dir.join(format!("{table}.jsonl"));
+    if path.is_file() {
+        let reader = BufReader::new(File::open(&path)?);
+        let rows = reader.lines().filter(|line| !matches!(line, Ok(l) if l.trim().is_empty())).map(
+            move |line| {
+                let line = line?;
+                serde_json::from_str(&line).with_context(|| format!("格式错误：{}", line))
+            },
+        );
+        return Ok(Some(Box::new(rows)));
+    }
+
+    let path = dir.join(format!("{table}.csv"));
+    if path.is_file() {
+        let mut reader = csv::Reader::from_path(&path)?;
+        let header = reader.headers()?.clone();
+        let rows = reader.into_records().map(move |record| {
+            let record = record?;
+            let row = header
+                .iter()
+                .zip(record.iter())
+                .map(|(k, v)| {
+                    let v = if v == CSV_NULL { Value::Null } else { Value::from(v) };
+                    (k.to_string(), v)
+                })
+                .collect();
+            Ok(row)
+        });
+        return Ok(Some(Box::new(rows)));
+    }
+
+    Ok(None)
+}
+
+/// 获取表中所有列的名称和声明的类型
+async fn table_columns(
+    tx: &mut Transaction<'_, Sqlite>,
+    table: &str,
+) -> Result<Vec<(String, String)>> {
+    let sql = format!("SELECT name, type FROM pragma_table_info('{table}')");
+    Ok(sqlx::query_as(&sql).fetch_all(&mut **tx).await?)
+}
+
+/// 按照列声明的类型转换字符串，CSV 中所有的值都是字符串，需要还原为数字
+///
+/// 规则和 sqlite 的类型亲和性相同，见 https://www.sqlite.org/datatype3.html#type_affinity
+fn typed_value(value: &Value, declared: &str) -> Value {
+    let Value::String(s) = value else { return value.clone() };
+    let declared = declared.to_uppercase();
+    let text = ["CHAR", "CLOB", "TEXT"].iter().any(|t| declared.contains(t));
+    if declared.is_empty() || text || declared.contains("BLOB") {
+        return value.clone();
+    }
+    let real = ["REAL", "FLOA", "DOUB"].iter().any(|t| declared.contains(t));
+    match s.parse::<i64>() {
+        Ok(i) if declared.contains("INT") || !real => Some(Value::from(i)),
+        _ => s.parse::<f64>().ok().and_then(|f| Number::from_f64(f).map(Value::Number)),
+    }
+    .unwrap_or_else(|| value.clone())
+}
+
+/// 插入一条记录，如果已经存在则跳过，返回插入的记录数
+async fn insert_row(
+    tx: &mut Transaction<'_, Sqlite>,
+    table: &Table,
+    columns: &[(String, String)],
+    mut row: Map<String, Value>,
+) -> Result<u64> {
+    // 列名会被拼接到 SQL 中，因此只接受表中实际存在的列
+    for (key, value) in row.iter_mut() {
+        match columns.iter().find(|(name, _)| name == key) {
+            Some((_, declared)) => *value = typed_value(value, declared),
+            None => bail!("{} 表中没有 {} 列", table.name, key),
+        }
+    }
+    if let Some(dedup) = table.dedup {
+        if let Some(key) = dedup.iter().find(|k| !row.contains_key(**k)) {
+            bail!("{} 表的记录缺少 {} 列", table.name, key);
+        }
+        row.remove("id");
+    }
+    let keys = row.keys().map(String::as_str).collect::<Vec<_>>();
+    let placeholders = vec!["?"; keys.len()].join(", ");
+
+    let sql = match table.dedup {
+        None => format!(
+            "INSERT OR IGNORE INTO {} ({}) VALUES ({})",
+            table.name,
+            keys.join(", "),
+            placeholders
+        ),
+        Some(dedup) => {
+            let cond = dedup.iter().map(|k| format!("{k} IS ?")).collect::<Vec<_>>();
+            format!(
+                "INSERT INTO {0} ({1}) SELECT {2} WHERE NOT EXISTS (SELECT 1 FROM {0} WHERE {3})",
+                table.name,
+                keys.join(", "),
+                placeholders,
+                cond.join(" AND ")
+            )
+        }
+    };
+
+    let mut query = sqlx::query(&sql);
+    let values = row.values().chain(table.dedup.unwrap_or_default().iter().map(|k| &row[*k]));
+    for value in values {
+        query = match value {
+            Value::Null => query.bind(None::<i64>),
+            Value::Bool(b) => query.bind(*b),
+            Value::Number(n) if n.is_i64() => query.bind(n.as_i64()),
+            Value::Number(n) => query.bind(n.as_f64()),
+            Value::String(s) => query.bind(s.clone()),
+            v => query.bind(v.to_string()),
+        };
+    }
+    Ok(query.execute(&mut **tx).await?.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 读取所有需要导出的表的内容
+    async fn dump() -> Vec<Vec<Map<String, Value>>> {
+        let mut tables = vec![];
+        for table in TABLES {
+            let sql = format!("SELECT * FROM {} ORDER BY rowid", table.name);
+            let rows = sqlx::query(&sql).fetch_all(&*DB).await.unwrap();
+            let mut rows = rows.iter().map(row_to_json).collect::<Vec<_>>();
+            // 自增主键在导入时会重新生成
+            if table.dedup.is_some() {
+                rows.iter_mut().for_each(|row| drop(row.remove("id")));
+            }
+            tables.push(rows);
+        }
+        tables
+    }
+
+    #[test]
+    fn csv_types() {
+        let typed = |v: &str, t| typed_value(&Value::from(v), t);
+        assert_eq!(typed("-100", "INTEGER"), Value::from(-100));
+        assert_eq!(typed("0.75", "FLOAT"), Value::from(0.75));
+        assert_eq!(typed("1", "REAL"), Value::from(1.0));
+        assert_eq!(typed("1", "BOOLEAN"), Value::from(1));
+        assert_eq!(typed("2023-06-17", "DATE"), Value::from("2023-06-17"));
+        assert_eq!(typed("-1001234567890", "TEXT"), Value::from("-1001234567890"));
+        assert_eq!(typed("0123456789", "TEXT"), Value::from("0123456789"));
+    }
+
+    #[tokio::test]
+    async fn export_import() {
+        std::env::set_var("DATABASE_URL", "sqlite::memory:");
+        sqlx::query(
+            r#"
+            INSERT INTO gallery (id, token, title, title_jp, tags, pages, parent, deleted, posted, favorite)
+            VALUES (1, '0123abcdef', 'a', NULL, '{"female":["lolicon"]}', 2, NULL, FALSE, '2023-06-17 01:00:00', 10),
+                (2, 'abcdef0123', 'b', 'b', '{}', 1, 1, TRUE, '2023-07-01 00:00:00', NULL);
+            INSERT INTO message (id, channel_id, gallery_id, publish_date)
+            VALUES (10, '-1001234567890', 1, '2023-06-17'), (11, '-1001234567890', 2, '2023-07-01');
+            INSERT INTO telegraph (gallery_id, url) VALUES (1, 'https://telegra.ph/a');
+            INSERT INTO image (id, hash, url) VALUES (100, '0123456789', '/file/a.jpg');
+            INSERT INTO page (gallery_id, page, image_id) VALUES (1, 1, 100), (2, 1, 100);
+            INSERT INTO poll (id, gallery_id, score, old_vote) VALUES (1000, 1, 0.75, '[1,2,3,4,5]');
+            INSERT INTO vote (user_id, poll_id, option, vote_time)
+            VALUES (1, 1000, 5, '2023-06-18 00:00:00');
+            INSERT INTO challenge_history (user_id, gallery_id, page, success, answer_time, chat_id)
+            VALUES (1, 1, 1, TRUE, '2023-06-18 00:00:00', -100);
+            "#,
+        )
+        .execute(&*DB)
+        .await
+        .unwrap();
+        let expected = dump().await;
+
+        for format in [BackupFormat::Jsonl, BackupFormat::Csv] {
+            let dir = std::env::temp_dir().join(format!("exloli-backup-test-{format}"));
+            export(&dir, format, None, None).await.unwrap();
+            for table in TABLES {
+                let sql = format!("DELETE FROM {}", table.name);
+                sqlx::query(&sql).execute(&*DB).await.unwrap();
+            }
+            import(&dir).await.unwrap();
+            assert_eq!(dump().await, expected, "{format}");
+
+            // 只有一端的日期范围
+            let since = NaiveDate::from_ymd_opt(2023, 6, 20);
+            let counts = export(&dir, format, since, None).await.unwrap();
+            assert!(counts.contains(&("gallery", 1)), "{counts:?}");
+            let until = NaiveDate::from_ymd_opt(2023, 6, 17);
+            let counts = export(&dir, format, None, until).await.unwrap();
+            assert!(counts.contains(&("gallery", 1)) && counts.contains(&("vote", 1)));
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
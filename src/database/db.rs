@@ -1,4 +1,5 @@
 use std::env;
+use std::str::FromStr;
 
 use futures::executor::block_on;
 use once_cell::sync::Lazy;
@@ -12,11 +13,15 @@ pub static DB: Lazy<SqlitePool> = Lazy::new(|| {
 
 pub async fn get_connection_pool(url: &str) -> SqlitePool {
     info!("初始化数据库连接：{}", url);
-    let options = SqliteConnectOptions::new()
+    // 以 sqlite: 开头时按连接字符串解析，例如 sqlite::memory:，否则视为文件路径
+    let options = match url.starts_with("sqlite:") {
+        true => SqliteConnectOptions::from_str(url).expect("数据库连接字符串格式错误"),
+        false => SqliteConnectOptions::new().filename(url),
+    };
+    let options = options
         .journal_mode(SqliteJournalMode::Wal)
         .synchronous(SqliteSynchronous::Normal)
         .foreign_keys(false)
-        .filename(url)
         .create_if_missing(true);
     let pool = SqlitePoolOptions::new().connect_with(options).await.expect("数据库连接失败");
     info!("检查数据库迁移");
@@ -1,4 +1,5 @@
 use std::ops::Deref;
+use std::str::FromStr;
 
 use chrono::prelude::*;
 use chrono::Duration;
@@ -66,7 +67,18 @@ impl GalleryEntity {
             g.posted,
         )
             .execute(&*DB)
-            .await
+            .await?;
+        let tags = flatten_tags(&g.tags);
+        sqlx::query!("DELETE FROM gallery_fts WHERE rowid = ?", id).execute(&*DB).await?;
+        sqlx::query!(
+            "INSERT INTO gallery_fts (rowid, title, title_jp, tags) VALUES (?, ?, ?, ?)",
+            id,
+            g.title,
+            g.title_jp,
+            tags,
+        )
+        .execute(&*DB)
+        .await
     }
 
     /// 根据 ID 获取一条记录
@@ -116,9 +128,133 @@ impl GalleryEntity {
     /// 彻底删除一个画廊
     #[tracing::instrument(level = Level::DEBUG)]
     pub async fn delete(id: i32) -> Result<SqliteQueryResult> {
+        sqlx::query!("DELETE FROM gallery_fts WHERE rowid = ?", id).execute(&*DB).await?;
         sqlx::query!("DELETE FROM gallery WHERE id = ?", id).execute(&*DB).await
     }
 
+    /// 根据 gallery 表重建全文索引，用于直接向 gallery 表写入数据之后
+    pub async fn rebuild_index() -> Result<SqliteQueryResult> {
+        sqlx::query!("DELETE FROM gallery_fts").execute(&*DB).await?;
+        sqlx::query!(
+            r#"INSERT INTO gallery_fts (rowid, title, title_jp, tags)
+            SELECT gallery.id, gallery.title, gallery.title_jp, char(10) || ifnull((
+                SELECT group_concat(
+                    ns.key || ':' || replace(tag.value, ' | ', char(10) || ns.key || ':'),
+                    char(10)
+                )
+                FROM json_each(CASE WHEN json_valid(gallery.tags) THEN gallery.tags ELSE '{}' END) AS ns,
+                    json_each(ns.value) AS tag
+            ), '') || char(10)
+            FROM gallery"#
+        )
+        .execute(&*DB)
+        .await
+    }
+
+    /// 在标题和 tag 中搜索未删除的画廊，结果按分数从高到低排列
+    ///
+    /// 返回 符合条件的画廊总数、画廊及其分数
+    pub async fn search(
+        search: &GallerySearch,
+        limit: i32,
+        offset: i32,
+    ) -> Result<(i32, Vec<(Self, Option<f32>)>)> {
+        let mut binds = vec![];
+        let from = search_from(search, &mut binds);
+
+        let sql = format!("SELECT COUNT(*) {}", from);
+        let mut query = sqlx::query_scalar(&sql);
+        for bind in &binds {
+            query = query.bind(bind);
+        }
+        let total = query.fetch_one(&*DB).await?;
+
+        let sql = format!(
+            "SELECT gallery.*, poll.score {} ORDER BY poll.score IS NULL, poll.score DESC, gallery.posted DESC LIMIT ? OFFSET ?",
+            from
+        );
+        let mut query = sqlx::query(&sql);
+        for bind in &binds {
+            query = query.bind(bind);
+        }
+        let rows = query.bind(limit).bind(offset).fetch_all(&*DB).await?;
+        let galleries = rows
+            .iter()
+            .map(|row| Ok((Self::from_row(row)?, row.try_get("score")?)))
+            .collect::<Result<_>>()?;
+        Ok((total, galleries))
+    }
+
+    /// 随机选择一个符合条件、已经发布到频道并且有投票的画廊
+    ///
+    /// 返回 画廊及其投票 ID
+    pub async fn random(search: &GallerySearch) -> Result<Option<(Self, i64)>> {
+        let mut binds = vec![];
+        let from = search_from(search, &mut binds);
+        let sql = format!(
+            r#"SELECT gallery.*, poll.id AS poll_id {}
+            AND poll.score IS NOT NULL
+            AND gallery.id IN (SELECT gallery_id FROM message)
+            ORDER BY RANDOM() LIMIT 1"#,
+            from
+        );
+        let mut query = sqlx::query(&sql);
+        for bind in &binds {
+            query = query.bind(bind);
+        }
+        match query.fetch_optional(&*DB).await? {
+            Some(row) => Ok(Some((Self::from_row(&row)?, row.try_get("poll_id")?))),
+            None => Ok(None),
+        }
+    }
+
+    /// 根据用户的投票推荐其还没有投过票的画廊
+    ///
+    /// 用户投过票的画廊中的每个 tag 会得到一个权重，“太棒了”为 +2，“我瞎了”为 -2，
+    /// 画廊的推荐度为其所有 tag 的权重之和，推荐度相同时按分数排列
+    ///
+    /// 返回 画廊、推荐度及其分数
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn recommend(user_id: i64, limit: i32) -> Result<Vec<(Self, i32, f32)>> {
+        let rows = sqlx::query(
+            r#"WITH voted AS (
+                SELECT poll.gallery_id, vote.option FROM vote JOIN poll ON poll.id = vote.poll_id
+                WHERE vote.user_id = ?
+            ), affinity AS (
+                SELECT ns.key AS namespace, tag.value AS tag, SUM(voted.option - 3) AS weight
+                FROM voted
+                JOIN gallery ON gallery.id = voted.gallery_id,
+                    json_each(CASE WHEN json_valid(gallery.tags) THEN gallery.tags ELSE '{}' END) AS ns,
+                    json_each(ns.value) AS tag
+                WHERE ns.key NOT IN ('language', 'reclass')
+                GROUP BY ns.key, tag.value
+                HAVING weight != 0
+            ), scores AS (
+                SELECT gallery_id, MAX(score) AS score FROM poll GROUP BY gallery_id
+            )
+            SELECT gallery.*, scores.score, SUM(affinity.weight) AS affinity
+            FROM gallery
+            JOIN scores ON scores.gallery_id = gallery.id,
+                json_each(CASE WHEN json_valid(gallery.tags) THEN gallery.tags ELSE '{}' END) AS ns,
+                json_each(ns.value) AS tag
+            JOIN affinity ON affinity.namespace = ns.key AND affinity.tag = tag.value
+            WHERE gallery.deleted = FALSE
+                AND gallery.id IN (SELECT gallery_id FROM message)
+                AND gallery.id NOT IN (SELECT gallery_id FROM voted)
+            GROUP BY gallery.id
+            HAVING affinity > 0
+            ORDER BY affinity DESC, scores.score DESC
+            LIMIT ?"#,
+        )
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(&*DB)
+        .await?;
+        rows.iter()
+            .map(|row| Ok((Self::from_row(row)?, row.try_get("affinity")?, row.try_get("score")?)))
+            .collect()
+    }
+
     /// 查询自指定日期以来的本子，结果按分数从高到低排列
     /// 返回 分数、标题、画廊 ID
     #[tracing::instrument(level = Level::DEBUG)]
@@ -147,6 +283,14 @@ impl GalleryEntity {
         Ok(record.into_iter().map(|x| (x.score as f32, x.title, x.id as i32)).collect())
     }
 
+    /// 列出指定日期以来发布的画廊
+    pub async fn list_since(since: NaiveDate) -> Result<Vec<Self>> {
+        sqlx::query_as("SELECT * FROM gallery WHERE deleted = FALSE AND posted >= ?")
+            .bind(since)
+            .fetch_all(&*DB)
+            .await
+    }
+
     /// 列出所有 80 分以上或最近两个月上传的画廊
     pub async fn list_scans() -> Result<Vec<Self>> {
         let since = Utc::now().date_naive() - Duration::days(60);
@@ -162,6 +306,153 @@ impl GalleryEntity {
     }
 }
 
+/// 画廊的搜索条件
+///
+/// 由空格分隔，支持 关键词、namespace:tag、-tag，以及 score>=80 这样的分数条件，
+/// 包含空格的 tag 可以用引号括起来或者用 _ 代替空格
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GallerySearch {
+    /// 标题或 tag 中需要包含的关键词
+    pub keywords: Vec<String>,
+    /// 需要包含的 tag，形如 namespace:tag
+    pub tags: Vec<String>,
+    /// 不能包含的 tag，可以省略 namespace
+    pub exclude: Vec<String>,
+    /// 最低分数，0 ~ 1
+    pub min_score: Option<f32>,
+    /// 最高分数，0 ~ 1
+    pub max_score: Option<f32>,
+}
+
+impl FromStr for GallerySearch {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut search = Self::default();
+        for term in split_terms(s) {
+            let score = term.strip_prefix("score").filter(|s| s.starts_with(['>', '<', ':', '：']));
+            if let Some(cond) = score {
+                let (op, value) = match cond.find(|c: char| c.is_ascii_digit() || c == '.') {
+                    Some(i) => cond.split_at(i),
+                    None => return Err(format!("分数条件格式错误：{term}")),
+                };
+                let value = match value.parse::<f32>() {
+                    Ok(value @ 0.0..=100.0) => value / 100.,
+                    _ => return Err(format!("分数应当在 0 ~ 100 之间：{term}")),
+                };
+                match op {
+                    ">" | ">=" | ":" | "：" => search.min_score = Some(value),
+                    "<" | "<=" => search.max_score = Some(value),
+                    _ => return Err(format!("分数条件格式错误：{term}")),
+                }
+            } else if let Some(tag) = term.strip_prefix('-').filter(|t| !t.is_empty()) {
+                search.exclude.push(tag.replace('_', " ").replace('：', ":"));
+            } else if term.contains([':', '：']) {
+                search.tags.push(term.replace('_', " ").replace('：', ":"));
+            } else {
+                search.keywords.push(term);
+            }
+        }
+        if search == Self::default() {
+            return Err("请输入搜索条件".to_string());
+        }
+        Ok(search)
+    }
+}
+
+/// 按空格拆分搜索条件，引号中的空格不会被拆分
+fn split_terms(s: &str) -> Vec<String> {
+    let mut terms = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in s.chars() {
+        match c {
+            '"' | '“' | '”' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    terms.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        terms.push(current);
+    }
+    terms
+}
+
+/// 生成搜索画廊时使用的 FROM 和 WHERE 子句，poll.score 为画廊的分数
+fn search_from(search: &GallerySearch, binds: &mut Vec<String>) -> String {
+    let mut conds = vec!["gallery.deleted = FALSE".to_string()];
+    for keyword in &search.keywords {
+        conds.push(format!("gallery.id IN ({})", fts_condition(None, keyword, binds)));
+    }
+    for tag in &search.tags {
+        let tag = anchor_tag(tag);
+        conds.push(format!("gallery.id IN ({})", fts_condition(Some("tags"), &tag, binds)));
+    }
+    for tag in &search.exclude {
+        let tag = anchor_tag(tag);
+        conds.push(format!("gallery.id NOT IN ({})", fts_condition(Some("tags"), &tag, binds)));
+    }
+    if let Some(score) = search.min_score {
+        conds.push(format!("poll.score >= {}", score));
+    }
+    if let Some(score) = search.max_score {
+        conds.push(format!("poll.score <= {}", score));
+    }
+    // NOTE: 早期的画廊可能有多个投票，只取分数最高的一个，此时 id 为该投票的 ID
+    format!(
+        r#"FROM gallery
+        LEFT JOIN (SELECT id, gallery_id, MAX(score) AS score FROM poll GROUP BY gallery_id) AS poll
+            ON poll.gallery_id = gallery.id
+        WHERE {}"#,
+        conds.join(" AND ")
+    )
+}
+
+/// 将 tag 展开为每行一个的 namespace:tag，用于全文索引
+///
+/// 形如 nekogen | miyauchi takeshi 的 tag 会被展开为两行，和 gallery_fts 迁移中的处理一致，
+/// 首尾也会加上换行，以便按整行匹配 tag
+fn flatten_tags(tags: &IndexMap<String, Vec<String>>) -> String {
+    let tags = tags.iter().flat_map(|(ns, tags)| {
+        tags.iter().flat_map(|t| t.split(" | ")).map(move |t| format!("{ns}:{t}"))
+    });
+    format!("\n{}\n", tags.collect::<Vec<_>>().join("\n"))
+}
+
+/// 用换行将 tag 包围起来，避免 male:x 匹配到 female:x，或者 artist:abc 匹配到 artist:abcdef
+///
+/// 省略 namespace 时只匹配冒号之后的部分
+fn anchor_tag(tag: &str) -> String {
+    match tag.contains(':') {
+        true => format!("\n{tag}\n"),
+        false => format!(":{tag}\n"),
+    }
+}
+
+/// 生成在全文索引中查找指定内容的子查询，column 为空时查找所有列
+fn fts_condition(column: Option<&str>, term: &str, binds: &mut Vec<String>) -> String {
+    // NOTE: trigram 分词无法用 MATCH 查找少于 3 个字符的内容，此时改用 LIKE
+    if term.chars().count() >= 3 {
+        let phrase = format!("\"{}\"", term.replace('"', "\"\""));
+        binds.push(match column {
+            Some(column) => format!("{column} : {phrase}"),
+            None => phrase,
+        });
+        "SELECT rowid FROM gallery_fts WHERE gallery_fts MATCH ?".to_string()
+    } else {
+        let pattern = term.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+        binds.push(format!("%{pattern}%"));
+        let column = column.unwrap_or(
+            "(title || char(10) || ifnull(title_jp, '') || char(10) || ifnull(tags, ''))",
+        );
+        format!("SELECT rowid FROM gallery_fts WHERE {column} LIKE ? ESCAPE '\\'")
+    }
+}
+
 impl<'q> Decode<'q, Sqlite> for TagsEntity {
     fn decode(
         value: <Sqlite as HasValueRef<'q>>::ValueRef,
@@ -192,3 +483,124 @@ impl Deref for TagsEntity {
         &self.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::get_connection_pool;
+
+    #[test]
+    fn parse_search() {
+        let search: GallerySearch =
+            r#"foo female:big_breasts -male:"yaoi" -"sole male" score>=80 score<95.5"#
+                .parse()
+                .unwrap();
+        assert_eq!(search.keywords, vec!["foo"]);
+        assert_eq!(search.tags, vec!["female:big breasts"]);
+        assert_eq!(search.exclude, vec!["male:yaoi", "sole male"]);
+        assert_eq!(search.min_score, Some(0.8));
+        assert_eq!(search.max_score, Some(0.955));
+
+        let search: GallerySearch = "score:60 artist：someone".parse().unwrap();
+        assert_eq!(search.min_score, Some(0.6));
+        assert_eq!(search.tags, vec!["artist:someone"]);
+        let search: GallerySearch = "score<=20".parse().unwrap();
+        assert_eq!(search.max_score, Some(0.2));
+
+        assert!("".parse::<GallerySearch>().is_err());
+        assert!("score>".parse::<GallerySearch>().is_err());
+        assert!("score>101".parse::<GallerySearch>().is_err());
+        assert!("score>>80".parse::<GallerySearch>().is_err());
+        // 单独的 - 视为关键词
+        assert_eq!("-".parse::<GallerySearch>().unwrap().keywords, vec!["-"]);
+    }
+
+    #[test]
+    fn split() {
+        assert_eq!(split_terms("  a  b\tc "), vec!["a", "b", "c"]);
+        assert_eq!(split_terms(r#"a "b c" d"#), vec!["a", "b c", "d"]);
+        assert_eq!(split_terms("“中 文”x"), vec!["中 文x"]);
+        assert_eq!(split_terms(r#"-"sole male""#), vec!["-sole male"]);
+        assert!(split_terms("   ").is_empty());
+    }
+
+    #[test]
+    fn fts() {
+        let mut binds = vec![];
+        let sql = fts_condition(None, r#"a"bc"#, &mut binds);
+        assert_eq!(sql, "SELECT rowid FROM gallery_fts WHERE gallery_fts MATCH ?");
+        assert_eq!(binds, vec![r#""a""bc""#]);
+
+        let mut binds = vec![];
+        fts_condition(Some("tags"), "female:ahegao", &mut binds);
+        assert_eq!(binds, vec![r#"tags : "female:ahegao""#]);
+
+        // 少于 3 个字符时使用 LIKE，并转义通配符
+        let mut binds = vec![];
+        let sql = fts_condition(Some("tags"), "%_", &mut binds);
+        assert_eq!(sql, r"SELECT rowid FROM gallery_fts WHERE tags LIKE ? ESCAPE '\'");
+        assert_eq!(binds, vec![r"%\%\_%"]);
+
+        let mut binds = vec![];
+        let sql = fts_condition(None, r"中\", &mut binds);
+        assert!(sql.contains("title || char(10)"));
+        assert_eq!(binds, vec![r"%中\\%"]);
+    }
+
+    #[test]
+    fn flatten() {
+        let tags = IndexMap::from([
+            ("artist".to_string(), vec!["nekogen | miyauchi takeshi".to_string()]),
+            ("female".to_string(), vec!["lolicon".to_string(), "big breasts".to_string()]),
+        ]);
+        assert_eq!(
+            flatten_tags(&tags),
+            "\nartist:nekogen\nartist:miyauchi takeshi\nfemale:lolicon\nfemale:big breasts\n"
+        );
+        assert_eq!(flatten_tags(&IndexMap::new()), "\n\n");
+    }
+
+    #[tokio::test]
+    async fn search_whole_tag() {
+        // 使用单独的内存数据库，避免和其他测试互相影响
+        let pool = get_connection_pool("sqlite::memory:").await;
+        let galleries = [(1, "female", "yaoi"), (2, "male", "yaoi"), (3, "artist", "abcdef")];
+        for (id, ns, tag) in galleries {
+            sqlx::query(
+                "INSERT INTO gallery (id, token, title, tags, pages, deleted, posted, favorite)
+                VALUES (?, '', '', '{}', 1, FALSE, '2023-06-17 01:00:00', 0)",
+            )
+            .bind(id)
+            .execute(&pool)
+            .await
+            .unwrap();
+            let tags = IndexMap::from([(ns.to_string(), vec![tag.to_string()])]);
+            sqlx::query("INSERT INTO gallery_fts (rowid, title, tags) VALUES (?, '', ?)")
+                .bind(id)
+                .bind(flatten_tags(&tags))
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        let search = |query: &'static str| {
+            let pool = pool.clone();
+            async move {
+                let mut binds = vec![];
+                let from = search_from(&query.parse().unwrap(), &mut binds);
+                let sql = format!("SELECT gallery.id {from} ORDER BY gallery.id");
+                let mut query = sqlx::query_scalar::<_, i32>(&sql);
+                for bind in &binds {
+                    query = query.bind(bind);
+                }
+                query.fetch_all(&pool).await.unwrap()
+            }
+        };
+        assert_eq!(search("male:yaoi").await, vec![2]);
+        assert_eq!(search("female:yaoi").await, vec![1]);
+        assert_eq!(search("-male:yaoi").await, vec![1, 3]);
+        assert_eq!(search("-yaoi").await, vec![3]);
+        assert_eq!(search("artist:abc").await, Vec::<i32>::new());
+        assert_eq!(search("artist:abcdef").await, vec![3]);
+    }
+}
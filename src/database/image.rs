@@ -25,6 +25,9 @@ pub struct ImageEntity {
 }
 
 impl ImageEntity {
+    /// 没有 fileindex 的图片的起始 ID
+    pub const LOCAL_ID_START: u32 = 1 << 31;
+
     /// 创建一条记录
     #[tracing::instrument(level = Level::DEBUG)]
     pub async fn create(id: u32, hash: &str, url: &str) -> Result<SqliteQueryResult> {
@@ -66,6 +69,25 @@ impl ImageEntity {
         .await
     }
 
+    /// 创建一条本地导入或者从归档中得到的图片的记录，返回分配的 ID
+    ///
+    /// 这些图片没有 fileindex，因此从 LOCAL_ID_START 开始分配，避免和 E 站的 fileindex 冲突
+    /// NOTE: 扫描和 bot 可能同时上传，因此 ID 需要在插入的同一条语句中分配
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn create_local(hash: &str, url: &str) -> Result<u32> {
+        sqlx::query_scalar!(
+            r#"INSERT INTO image (id, hash, url)
+            VALUES ((SELECT IFNULL(MAX(id) + 1, ?) FROM image WHERE id >= ?), ?, ?)
+            RETURNING id as "id: u32""#,
+            Self::LOCAL_ID_START,
+            Self::LOCAL_ID_START,
+            hash,
+            url
+        )
+        .fetch_one(&*DB)
+        .await
+    }
+
     pub fn url(&self) -> String {
         if self.url.starts_with("/file/") {
             format!("https://telegra.ph{}", self.url)
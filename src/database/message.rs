@@ -16,6 +16,8 @@ pub struct MessageEntity {
     pub gallery_id: i32,
     /// 消息发布日期
     pub publish_date: NaiveDate,
+    /// 消息内容的 hash，旧消息可能为空
+    pub text_hash: Option<String>,
 }
 
 impl MessageEntity {
@@ -45,7 +47,8 @@ impl MessageEntity {
                 id as "id: i32",
                 channel_id,
                 gallery_id as "gallery_id: i32",
-                publish_date
+                publish_date,
+                text_hash
             FROM message WHERE id = ? AND channel_id = ?
             "#,
             id,
@@ -63,6 +66,44 @@ impl MessageEntity {
             .await
     }
 
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn update_text_hash(id: i32, hash: &str) -> Result<SqliteQueryResult> {
+        let channel_id = CHANNEL_ID.get().unwrap();
+        sqlx::query!(
+            "UPDATE message SET text_hash = ? WHERE id = ? AND channel_id = ?",
+            hash,
+            id,
+            channel_id
+        )
+        .execute(&*DB)
+        .await
+    }
+
+    /// 列出在指定日期范围内（包含两端）发布的消息，按发布顺序排列
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn list(start: NaiveDate, end: NaiveDate) -> Result<Vec<MessageEntity>> {
+        let channel_id = CHANNEL_ID.get().unwrap();
+        sqlx::query_as!(
+            MessageEntity,
+            r#"
+            SELECT
+                id as "id: i32",
+                channel_id,
+                gallery_id as "gallery_id: i32",
+                publish_date,
+                text_hash
+            FROM message
+            WHERE channel_id = ? AND publish_date BETWEEN ? AND ?
+            ORDER BY id
+            "#,
+            channel_id,
+            start,
+            end
+        )
+        .fetch_all(&*DB)
+        .await
+    }
+
     #[tracing::instrument(level = Level::DEBUG)]
     pub async fn get_by_gallery(gid: i32) -> Result<Option<MessageEntity>> {
         let channel_id = CHANNEL_ID.get().unwrap();
@@ -73,7 +114,8 @@ impl MessageEntity {
                 id as "id: i32",
                 channel_id,
                 gallery_id as "gallery_id: i32",
-                publish_date
+                publish_date,
+                text_hash
             FROM message
             WHERE gallery_id = ? AND channel_id = ?
             ORDER BY publish_date DESC
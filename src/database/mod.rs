@@ -1,3 +1,5 @@
+mod archive_request;
+mod backup;
 mod challenge;
 mod db;
 mod gallery;
@@ -5,12 +7,21 @@ mod image;
 mod invite_link;
 mod message;
 mod poll;
+mod rejected_gallery;
+mod scheduled_task;
+mod stats;
 mod telegraph;
 
+pub use archive_request::*;
+pub use backup::{export, import, BackupFormat};
 pub use challenge::*;
+pub use db::get_connection_pool;
 pub use gallery::*;
 pub use image::*;
 pub use invite_link::*;
 pub use message::*;
 pub use poll::*;
+pub use rejected_gallery::*;
+pub use scheduled_task::*;
+pub use stats::*;
 pub use telegraph::*;
@@ -30,6 +30,23 @@ pub struct VoteEntity {
     pub vote_time: NaiveDateTime,
 }
 
+/// 用户投票过的画廊
+#[derive(sqlx::FromRow, Debug)]
+pub struct UserVote {
+    /// 画廊 ID
+    pub gallery_id: i32,
+    /// 画廊 token
+    pub token: String,
+    /// 画廊标题
+    pub title: String,
+    /// 投票选项
+    pub option: i32,
+    /// 当前投票的分数
+    pub score: f32,
+    /// 投票时间
+    pub vote_time: NaiveDateTime,
+}
+
 impl PollEntity {
     /// 插入一条记录，如果冲突则忽略
     #[tracing::instrument(level = Level::DEBUG)]
@@ -43,6 +60,17 @@ impl PollEntity {
         .await
     }
 
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn get(id: i64) -> Result<Option<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"SELECT id, gallery_id as "gallery_id: i32", score as "score: f32", old_vote FROM poll WHERE id = ?"#,
+            id
+        )
+        .fetch_optional(&*DB)
+        .await
+    }
+
     #[tracing::instrument(level = Level::DEBUG)]
     pub async fn get_by_gallery(gallery_id: i32) -> Result<Option<Self>> {
         sqlx::query_as!(
@@ -111,6 +139,42 @@ impl VoteEntity {
         .execute(&*DB)
         .await
     }
+
+    /// 查询用户的投票记录，结果按投票时间从新到旧排列
+    ///
+    /// 返回 投票总数、投票过的画廊
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn list_by_user(
+        user_id: i64,
+        limit: i32,
+        offset: i32,
+    ) -> Result<(i32, Vec<UserVote>)> {
+        let total = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count: i32" FROM vote
+            JOIN poll ON poll.id = vote.poll_id
+            JOIN gallery ON gallery.id = poll.gallery_id
+            WHERE vote.user_id = ?"#,
+            user_id
+        )
+        .fetch_one(&*DB)
+        .await?;
+        let votes = sqlx::query_as!(
+            UserVote,
+            r#"SELECT gallery.id as "gallery_id: i32", gallery.token, gallery.title, vote.option as "option: i32",
+                poll.score as "score: f32", vote.vote_time
+            FROM vote
+            JOIN poll ON poll.id = vote.poll_id
+            JOIN gallery ON gallery.id = poll.gallery_id
+            WHERE vote.user_id = ?
+            ORDER BY vote.vote_time DESC LIMIT ? OFFSET ?"#,
+            user_id,
+            limit,
+            offset
+        )
+        .fetch_all(&*DB)
+        .await?;
+        Ok((total, votes))
+    }
 }
 
 /// 威尔逊得分
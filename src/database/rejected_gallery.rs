@@ -0,0 +1,52 @@
+use chrono::{NaiveDateTime, Utc};
+use sqlx::sqlite::SqliteQueryResult;
+use sqlx::Result;
+use tracing::Level;
+
+use super::db::DB;
+
+/// 不满足上传规则的画廊
+#[derive(sqlx::FromRow, Debug)]
+pub struct RejectedGalleryEntity {
+    /// 画廊 ID
+    pub id: i32,
+    /// 不满足的规则
+    pub reason: String,
+    /// 检查的时间
+    pub rejected_at: NaiveDateTime,
+}
+
+impl RejectedGalleryEntity {
+    /// 记录不满足上传规则的画廊，已经存在时更新检查时间
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn create(id: i32, reason: &str) -> Result<SqliteQueryResult> {
+        let now = Utc::now().naive_utc();
+        sqlx::query!(
+            "REPLACE INTO rejected_gallery (id, reason, rejected_at) VALUES (?, ?, ?)",
+            id,
+            reason,
+            now,
+        )
+        .execute(&*DB)
+        .await
+    }
+
+    /// 检查画廊是否在 since 之后被拒绝过
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn check(id: i32, since: NaiveDateTime) -> Result<bool> {
+        sqlx::query_scalar!(
+            "SELECT EXISTS(SELECT 1 FROM rejected_gallery WHERE id = ? AND rejected_at > ?)",
+            id,
+            since
+        )
+        .fetch_one(&*DB)
+        .await
+        .map(|x| x == Some(1))
+    }
+
+    /// 上传规则修改之后，清空所有记录
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn clear() -> Result<SqliteQueryResult> {
+        sqlx::query!("DELETE FROM rejected_gallery").execute(&*DB).await
+    }
+}
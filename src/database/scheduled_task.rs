@@ -0,0 +1,63 @@
+use chrono::NaiveDateTime;
+use sqlx::Result;
+use tracing::Level;
+
+use super::db::DB;
+
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct ScheduledTaskEntity {
+    /// 任务 ID
+    pub id: i64,
+    /// 会话 ID
+    pub chat_id: i64,
+    /// 消息 ID
+    pub message_id: i32,
+    /// JSON 格式的操作内容
+    pub action: String,
+    /// 计划执行时间
+    pub run_at: NaiveDateTime,
+}
+
+impl ScheduledTaskEntity {
+    /// 创建一条记录，返回任务 ID
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn create(
+        chat_id: i64,
+        message_id: i32,
+        action: &str,
+        run_at: NaiveDateTime,
+    ) -> Result<i64> {
+        sqlx::query!(
+            "INSERT INTO scheduled_task (chat_id, message_id, action, run_at) VALUES (?, ?, ?, ?)",
+            chat_id,
+            message_id,
+            action,
+            run_at,
+        )
+        .execute(&*DB)
+        .await
+        .map(|r| r.last_insert_rowid())
+    }
+
+    /// 列出所有尚未执行的任务，按执行时间排列
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn list() -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"SELECT id, chat_id, message_id as "message_id: i32", action, run_at FROM scheduled_task ORDER BY run_at"#
+        )
+        .fetch_all(&*DB)
+        .await
+    }
+
+    /// 删除一条记录，返回该记录删除前是否存在
+    ///
+    /// 执行任务前也会调用此方法，如果返回 false，说明任务已经被取消或者执行过了
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn delete(id: i64) -> Result<bool> {
+        sqlx::query!("DELETE FROM scheduled_task WHERE id = ?", id)
+            .execute(&*DB)
+            .await
+            .map(|r| r.rows_affected() > 0)
+    }
+}
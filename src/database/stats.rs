@@ -0,0 +1,155 @@
+use chrono::{Duration, NaiveDate, Utc};
+use sqlx::Result;
+use tracing::Level;
+
+use super::db::DB;
+
+/// 每个榜单显示的数量
+const TOP_LIMIT: i32 = 5;
+/// 按平均分排名时，至少需要的画廊数量，避免只有一本的画师排在最前面
+const TOP_MIN_COUNT: i32 = 3;
+
+/// 每个画廊分数最高的一个有效投票，即至少有一人投票或者有旧系统数据的投票
+const SCORES: &str = r#"(
+    SELECT gallery_id, MAX(score) AS score FROM poll
+    WHERE old_vote IS NOT NULL OR id IN (SELECT poll_id FROM vote)
+    GROUP BY gallery_id
+)"#;
+
+/// 频道的整体统计数据
+#[derive(Debug, Clone)]
+pub struct ChannelStats {
+    /// 画廊总数，包括已删除的画廊
+    pub galleries: i32,
+    /// 已删除的画廊数量
+    pub deleted: i32,
+    /// 最近 7 天发布到频道的画廊数量
+    pub last_week: i32,
+    /// 最近 30 天发布到频道的画廊数量
+    pub last_month: i32,
+    /// 投票总数
+    pub votes: i32,
+    /// 投过票的用户数量
+    pub voters: i32,
+    /// 最近 30 天投过票的用户数量
+    pub active_voters: i32,
+    /// 分数分布，第 i 项为 i * 10 ~ (i + 1) * 10 分的画廊数量
+    pub scores: [i32; 10],
+    /// 各个图床上的图片数量，按数量从多到少排列
+    pub hosts: Vec<(String, i32)>,
+}
+
+/// 某个 tag 的统计数据
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct TagStats {
+    pub namespace: String,
+    pub tag: String,
+    /// 包含该 tag 且有投票的画廊数量
+    pub count: i32,
+    /// 这些画廊的平均分数，0 ~ 1
+    pub score: f32,
+}
+
+impl ChannelStats {
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn get() -> Result<Self> {
+        let today = Utc::now().date_naive();
+        let (galleries, deleted): (i32, i32) =
+            sqlx::query_as("SELECT COUNT(*), IFNULL(SUM(deleted), 0) FROM gallery")
+                .fetch_one(&*DB)
+                .await?;
+        let last_week = published_since(today - Duration::days(7)).await?;
+        let last_month = published_since(today - Duration::days(30)).await?;
+
+        let (votes, voters): (i32, i32) =
+            sqlx::query_as("SELECT COUNT(*), COUNT(DISTINCT user_id) FROM vote")
+                .fetch_one(&*DB)
+                .await?;
+        let active_voters =
+            sqlx::query_scalar("SELECT COUNT(DISTINCT user_id) FROM vote WHERE vote_time >= ?")
+                .bind(today - Duration::days(30))
+                .fetch_one(&*DB)
+                .await?;
+
+        let mut scores = [0; 10];
+        let sql = format!(
+            "SELECT MIN(CAST(score * 10 AS INTEGER), 9), COUNT(*) FROM {} GROUP BY 1",
+            SCORES
+        );
+        let rows: Vec<(i32, i32)> = sqlx::query_as(&sql).fetch_all(&*DB).await?;
+        for (bucket, count) in rows {
+            scores[bucket.clamp(0, 9) as usize] = count;
+        }
+
+        // telegraph 上的图片只保存了路径，其他图床保存的是完整的 URL
+        let hosts = sqlx::query_as(
+            r#"SELECT host, COUNT(*) FROM (
+                SELECT CASE
+                    WHEN url LIKE '/%' THEN 'telegra.ph'
+                    ELSE substr(
+                        substr(url, instr(url, '://') + 3),
+                        1,
+                        instr(substr(url, instr(url, '://') + 3) || '/', '/') - 1
+                    )
+                END AS host
+                FROM image
+            )
+            GROUP BY host ORDER BY COUNT(*) DESC"#,
+        )
+        .fetch_all(&*DB)
+        .await?;
+
+        Ok(Self {
+            galleries,
+            deleted,
+            last_week,
+            last_month,
+            votes,
+            voters,
+            active_voters,
+            scores,
+            hosts,
+        })
+    }
+}
+
+impl TagStats {
+    /// 获取指定 namespace 中画廊数量最多的 tag
+    pub async fn top_by_count(namespaces: &[&str]) -> Result<Vec<Self>> {
+        Self::top(namespaces, "count DESC, score DESC", 1).await
+    }
+
+    /// 获取指定 namespace 中平均分最高的 tag，只统计有足够多画廊的 tag
+    pub async fn top_by_score(namespaces: &[&str]) -> Result<Vec<Self>> {
+        Self::top(namespaces, "score DESC, count DESC", TOP_MIN_COUNT).await
+    }
+
+    async fn top(namespaces: &[&str], order: &str, min_count: i32) -> Result<Vec<Self>> {
+        let placeholders = vec!["?"; namespaces.len()].join(", ");
+        let sql = format!(
+            r#"SELECT ns.key AS namespace, tag.value AS tag, COUNT(*) AS count, AVG(poll.score) AS score
+            FROM gallery
+            JOIN {} AS poll ON poll.gallery_id = gallery.id,
+                json_each(CASE WHEN json_valid(gallery.tags) THEN gallery.tags ELSE '{{}}' END) AS ns,
+                json_each(ns.value) AS tag
+            WHERE gallery.deleted = FALSE AND ns.key IN ({})
+            GROUP BY ns.key, tag.value
+            HAVING COUNT(*) >= ?
+            ORDER BY {} LIMIT ?"#,
+            SCORES, placeholders, order
+        );
+        let mut query = sqlx::query_as(&sql);
+        for namespace in namespaces {
+            query = query.bind(namespace);
+        }
+        query.bind(min_count).bind(TOP_LIMIT).fetch_all(&*DB).await
+    }
+}
+
+/// 指定日期以来发布到频道的画廊数量
+async fn published_since(date: NaiveDate) -> Result<i32> {
+    sqlx::query_scalar("SELECT COUNT(DISTINCT gallery_id) FROM message WHERE publish_date >= ?")
+        .bind(date)
+        .fetch_one(&*DB)
+        .await
+}
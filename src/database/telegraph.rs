@@ -9,6 +9,8 @@ pub struct TelegraphEntity {
     pub gallery_id: i32,
     /// telegraph 文章 URL
     pub url: String,
+    /// catbox 专辑 URL
+    pub album_url: Option<String>,
 }
 
 impl TelegraphEntity {
@@ -25,7 +27,7 @@ impl TelegraphEntity {
     pub async fn get(gallery_id: i32) -> Result<Option<TelegraphEntity>> {
         sqlx::query_as!(
             TelegraphEntity,
-            r#"SELECT gallery_id as "gallery_id: i32", url FROM telegraph WHERE gallery_id = ?"#,
+            r#"SELECT gallery_id as "gallery_id: i32", url, album_url FROM telegraph WHERE gallery_id = ?"#,
             gallery_id
         )
         .fetch_optional(&*DB)
@@ -37,4 +39,14 @@ impl TelegraphEntity {
             .execute(&*DB)
             .await
     }
+
+    pub async fn update_album(gallery_id: i32, album_url: &str) -> Result<SqliteQueryResult> {
+        sqlx::query!(
+            "UPDATE telegraph SET album_url = ? WHERE gallery_id = ?",
+            album_url,
+            gallery_id
+        )
+        .execute(&*DB)
+        .await
+    }
 }
@@ -5,13 +5,15 @@ use once_cell::sync::Lazy;
 use regex::Regex;
 use reqwest::header::*;
 use reqwest::Client;
-use scraper::{Html, Selector};
+use scraper::{ElementRef, Html, Selector};
 use serde::Serialize;
 use std::fmt::Debug;
 use std::time::Duration;
+use tokio::io::AsyncWriteExt;
 use tracing::{debug, error, info, Instrument};
 
 use super::error::*;
+use super::local::*;
 use super::types::*;
 use crate::utils::html::SelectorExtend;
 
@@ -137,7 +139,123 @@ impl EhClient {
     }
 
     #[tracing::instrument(skip(self))]
-    pub async fn archive_gallery(&self, url: &EhGalleryUrl) -> Result<()> {
+    pub async fn archive_gallery(
+        &self,
+        url: &EhGalleryUrl,
+        resolution: ArchiveResolution,
+        budget: Option<u64>,
+    ) -> Result<ArchiveResult> {
+        let query = self.archiver_query(url).await?;
+        let options = self.archive_options_with(&query).await?;
+
+        let cost = options.iter().find(|o| o.resolution == resolution).and_then(|o| o.cost);
+        let cost = match cost {
+            Some(cost) => cost,
+            None => return Ok(ArchiveResult::Unavailable { resolution }),
+        };
+        if let Some(budget) = budget.filter(|&budget| cost > budget) {
+            return Ok(ArchiveResult::OverBudget { resolution, cost, budget });
+        }
+
+        send!(self
+            .0
+            .post("https://exhentai.org/archiver.php")
+            .query(&query)
+            .form(&[("hathdl_xres", resolution.as_str())]))?;
+
+        Ok(ArchiveResult::Requested { resolution, cost })
+    }
+
+    /// 通过 archiver.php 直接下载整个画廊的归档并解压，比逐页下载少很多请求
+    ///
+    /// 下载过程中会将压缩包写入临时目录，解压出的文件会在返回的 LocalGallery drop 时删除
+    #[tracing::instrument(skip(self, gallery), fields(url = %gallery.url))]
+    pub async fn download_archive(
+        &self,
+        gallery: &EhGallery,
+        kind: ArchiveKind,
+        budget: Option<u64>,
+    ) -> Result<ArchiveDownloadResult> {
+        static RE: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r#"(?P<url>https?://(?P<host>[^/"']+)/archive/[^"'?]+)"#).unwrap()
+        });
+
+        let query = self.archiver_query(&gallery.url).await?;
+        let cost = {
+            let resp = send!(self.0.get("https://exhentai.org/archiver.php").query(&query))?;
+            let html = Html::parse_document(&resp.text().await?);
+            // 每种归档是一个 div，其中包含花费和提交 dltype 的表单
+            html.select(&selector!("input[name=dltype]"))
+                .find(|input| input.value().attr("value") == Some(kind.as_str()))
+                .and_then(|input| {
+                    input
+                        .ancestors()
+                        .filter_map(ElementRef::wrap)
+                        .find(|e| e.value().name() == "div")
+                })
+                .and_then(|div| div.select_text("strong"))
+                .and_then(|cost| ArchiveOption::parse_cost(&cost))
+        };
+        let cost = match cost {
+            Some(cost) => cost,
+            None => return Ok(ArchiveDownloadResult::Unavailable),
+        };
+        if let Some(budget) = budget.filter(|&budget| cost > budget) {
+            return Ok(ArchiveDownloadResult::OverBudget { cost, budget });
+        }
+
+        let resp = send!(self
+            .0
+            .post("https://exhentai.org/archiver.php")
+            .query(&query)
+            .form(&[("dltype", kind.as_str()), ("dlcheck", kind.label())]))?;
+        let text = resp.text().await?;
+        let captures =
+            RE.captures(&text).ok_or_else(|| EhError::ArchiveLinkNotFound(gallery.url.url()))?;
+        let (url, host) = (&captures["url"], &captures["host"]);
+        info!("下载归档：{}，花费 {} GP", url, cost);
+
+        let dir = std::env::temp_dir().join(format!("exloli-archive-{}", gallery.url.id()));
+        // 先创建 LocalGallery，这样下载失败时临时目录也会在 drop 时被删除
+        let local = LocalGallery::temporary(dir.clone());
+        tokio::fs::create_dir_all(&dir).await?;
+        let path = dir.join("archive.zip");
+        // NOTE: 下载服务器不是 E 站，需要覆盖默认的 Host，归档可能很大，也不能使用默认的超时时间
+        let mut resp = send!(self
+            .0
+            .get(url)
+            .query(&[("start", "1")])
+            .header(HOST, host)
+            .timeout(Duration::from_secs(60 * 60)))?;
+        let mut file = tokio::fs::File::create(&path).await?;
+        while let Some(chunk) = resp.chunk().await? {
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        drop(file);
+
+        let pages = gallery.pages.clone();
+        let original = kind == ArchiveKind::Original;
+        let local = tokio::task::spawn_blocking(move || {
+            let local = local.extract_archive(&path, &pages, original);
+            let _ = std::fs::remove_file(&path);
+            local
+        })
+        .await??;
+        debug!("归档中的图片数：{}", local.len());
+
+        Ok(ArchiveDownloadResult::Downloaded(local))
+    }
+
+    /// 获取画廊归档页面上各个分辨率的大小和花费
+    #[tracing::instrument(skip(self))]
+    pub async fn archive_options(&self, url: &EhGalleryUrl) -> Result<Vec<ArchiveOption>> {
+        let query = self.archiver_query(url).await?;
+        self.archive_options_with(&query).await
+    }
+
+    /// 从画廊页面获取访问归档页面需要的参数
+    async fn archiver_query(&self, url: &EhGalleryUrl) -> Result<[(&'static str, String); 3]> {
         static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"or=(?P<or>[0-9a-z-]+)").unwrap());
 
         let resp = send!(self.0.get(url.url()))?;
@@ -146,20 +264,41 @@ impl EhClient {
 
         let or = RE.captures(&onclick).and_then(|c| c.name("or")).unwrap().as_str();
 
-        send!(self
-            .0
-            .post("https://exhentai.org/archiver.php")
-            .query(&[("gid", &*url.id().to_string()), ("token", url.token()), ("or", or)])
-            .form(&[("hathdl_xres", "org")]))?;
+        Ok([
+            ("gid", url.id().to_string()),
+            ("token", url.token().to_owned()),
+            ("or", or.to_owned()),
+        ])
+    }
+
+    async fn archive_options_with(&self, query: &[(&str, String)]) -> Result<Vec<ArchiveOption>> {
+        let resp = send!(self.0.get("https://exhentai.org/archiver.php").query(query))?;
+        let html = Html::parse_document(&resp.text().await?);
+
+        // 每个分辨率是一个单元格，依次为分辨率、大小、花费，不可用的分辨率没有链接
+        let mut options = vec![];
+        for td in html.select(&selector!("td")) {
+            let texts = td.select_texts("p");
+            let resolution = match texts.first().and_then(|s| s.parse().ok()) {
+                Some(resolution) => resolution,
+                None => continue,
+            };
+            let available = td.select_attr("a", "onclick").is_some();
+            let size = texts.get(1).filter(|_| available).cloned();
+            let cost =
+                texts.get(2).filter(|_| available).and_then(|s| ArchiveOption::parse_cost(s));
+            options.push(ArchiveOption { resolution, size, cost });
+        }
+        debug!("归档选项：{:?}", options);
 
-        Ok(())
+        Ok(options)
     }
 
     #[tracing::instrument(skip(self))]
     pub async fn get_gallery(&self, url: &EhGalleryUrl) -> Result<EhGallery> {
         // NOTE: 由于 Html 是 !Send 的，为了避免它被包含在 Future 上下文中，这里将它放在一个单独的作用域内
         // 参见：https://rust-lang.github.io/async-book/07_workarounds/03_send_approximation.html
-        let (title, title_jp, parent, tags, favorite, mut pages, posted, mut next_page) = {
+        let (title, title_jp, parent, tags, favorite, mut pages, posted, uploader, mut next_page) = {
             let resp = send!(self.0.get(url.url()))?;
             let html = Html::parse_document(&resp.text().await?);
 
@@ -189,13 +328,16 @@ impl EhClient {
             let posted = &html.select_texts("td.gdt2")[0];
             let posted = NaiveDateTime::parse_from_str(posted, "%Y-%m-%d %H:%M")?;
 
+            // 上传者
+            let uploader = html.select_text("div#gdn a");
+
             // 每一页的 URL
             let pages = html.select_attrs("div#gdt a", "href");
 
             // 下一页的 URL
             let next_page = html.select_attr("table.ptb td:last-child a", "href");
 
-            (title, title_jp, parent, tags, favorite, pages, posted, next_page)
+            (title, title_jp, parent, tags, favorite, pages, posted, uploader, next_page)
         };
 
         while let Some(next_page_url) = &next_page {
@@ -223,13 +365,23 @@ impl EhClient {
             pages,
             posted,
             cover,
+            uploader,
         })
     }
 
+    /// 获取画廊的某一页的图片的 fileindex，不会请求图片本身
+    #[tracing::instrument(skip(self))]
+    pub async fn get_fileindex(&self, page: &EhPageUrl) -> Result<u32> {
+        let resp = send!(self.0.get(page.url()))?;
+        let html = Html::parse_document(&resp.text().await?);
+        let url = html.select_attr("img#img", "src").unwrap();
+        extract_fileindex(&url).ok_or(EhError::HaHUrlBroken(url))
+    }
+
     /// 获取画廊的某一页的图片的 fileindex 和实际地址和 nl
     #[tracing::instrument(skip(self))]
     pub async fn get_image_url(&self, page: &EhPageUrl) -> Result<(u32, String)> {
-        let resp = send!(self.0.get(&page.url()))?;
+        let resp = send!(self.0.get(page.url()))?;
         let (url, nl, fileindex) = {
             let html = Html::parse_document(&resp.text().await?);
             let url = html.select_attr("img#img", "src").unwrap();
@@ -241,7 +393,7 @@ impl EhClient {
         return if send!(self.0.head(&url)).is_ok() {
             Ok((fileindex, url))
         } else if nl.is_some() {
-            let resp = send!(self.0.get(&page.with_nl(&nl.unwrap()).url()))?;
+            let resp = send!(self.0.get(page.with_nl(&nl.unwrap()).url()))?;
             let html = Html::parse_document(&resp.text().await?);
             let url = html.select_attr("img#img", "src").unwrap();
             Ok((fileindex, url))
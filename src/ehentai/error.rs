@@ -14,4 +14,12 @@ pub enum EhError {
     DateTimeError(#[from] chrono::format::ParseError),
     #[error("h@h url broken: {0}")]
     HaHUrlBroken(String),
+    #[error("invalid archive resolution: {0}")]
+    InvalidResolution(String),
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("zip error: {0}")]
+    ZipError(#[from] zip::result::ZipError),
+    #[error("archive download link not found: {0}")]
+    ArchiveLinkNotFound(String),
 }
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use glob::glob;
+use sha1::{Digest, Sha1};
+use tracing::{debug, warn};
+use zip::result::ZipResult;
+
+use super::types::EhPageUrl;
+use crate::utils::natural_cmp;
+
+/// 直接下载归档的结果
+#[derive(Debug)]
+pub enum ArchiveDownloadResult {
+    /// 已下载并解压
+    Downloaded(LocalGallery),
+    /// 该类型的归档不可用
+    Unavailable,
+    /// 所需的 GP 超出了预算，没有下载
+    OverBudget { cost: u64, budget: u64 },
+}
+
+/// H@H 下载到本地的画廊
+///
+/// H@H 下载的目录名格式为 `标题 [gid]`，非原图分辨率为 `标题 [gid-1280x]`，
+/// 只有原图的 hash 能和画廊页面对上，所以只会匹配到原图
+#[derive(Debug, Default)]
+pub struct LocalGallery {
+    /// 图片 hash（sha1 前 10 位）到文件路径的映射
+    files: HashMap<String, PathBuf>,
+    /// 解压归档时使用的临时目录，会在 drop 时删除
+    temp_dir: Option<PathBuf>,
+}
+
+impl LocalGallery {
+    /// 在下载目录中查找指定画廊，并计算其中所有图片的 hash
+    pub async fn find(dir: &str, gallery_id: i32) -> io::Result<Option<Self>> {
+        let pattern = format!("{}/*[[]{}]", dir, gallery_id);
+        let path = match glob(&pattern).ok().and_then(|mut paths| paths.find_map(|p| p.ok())) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        debug!("找到本地画廊：{}", path.display());
+        let files = tokio::task::spawn_blocking(move || hash_files(&path)).await??;
+        Ok(Some(Self { files, temp_dir: None }))
+    }
+
+    /// 使用指定的文件创建，返回按照文件顺序排列的 hash
+    pub async fn from_files(paths: Vec<PathBuf>) -> io::Result<(Self, Vec<String>)> {
+        tokio::task::spawn_blocking(move || {
+            let mut files = HashMap::new();
+            let mut hashes = vec![];
+            for path in paths {
+                let hash = hash_file(&path)?;
+                hashes.push(hash.clone());
+                files.insert(hash, path);
+            }
+            Ok((Self { files, temp_dir: None }, hashes))
+        })
+        .await?
+    }
+
+    /// 创建一个空的画廊，dir 会在 drop 时删除，之后可以通过 extract_archive 将归档解压到其中
+    pub fn temporary(dir: PathBuf) -> Self {
+        Self { files: HashMap::new(), temp_dir: Some(dir) }
+    }
+
+    /// 将画廊的归档解压到临时目录，并和画廊页面对应起来
+    ///
+    /// 原图归档通过 hash 对应，重新采样的归档 hash 和页面对不上，只能按照文件名顺序对应，
+    /// 因此图片数量和页数不一致时无法确定对应关系，会直接返回错误
+    pub fn extract_archive(
+        mut self,
+        path: &Path,
+        pages: &[EhPageUrl],
+        original: bool,
+    ) -> ZipResult<Self> {
+        let dir = self.temp_dir.as_deref().expect("只能解压到临时目录");
+        let mut paths = extract_zip(path, dir)?;
+        paths.retain(|p| p.extension().is_some_and(|ext| ext != "txt"));
+        paths.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+
+        if original {
+            for path in paths {
+                self.files.insert(hash_file(&path)?, path);
+            }
+        } else {
+            if paths.len() != pages.len() {
+                let msg =
+                    format!("归档中的图片数量 {} 和画廊页数 {} 不一致", paths.len(), pages.len());
+                return Err(io::Error::new(io::ErrorKind::InvalidData, msg).into());
+            }
+            for (page, path) in pages.iter().zip(paths) {
+                self.files.insert(page.hash().to_owned(), path);
+            }
+        }
+        Ok(self)
+    }
+
+    /// 根据页面的 hash 获取本地文件
+    pub fn get(&self, hash: &str) -> Option<&Path> {
+        self.files.get(hash).map(PathBuf::as_path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+impl Drop for LocalGallery {
+    fn drop(&mut self) {
+        if let Some(dir) = &self.temp_dir {
+            if let Err(err) = fs::remove_dir_all(dir) {
+                warn!("删除临时目录失败：{} {}", dir.display(), err);
+            }
+        }
+    }
+}
+
+/// 将压缩包解压到指定目录，返回解压出的文件，会跳过不安全的路径，例如 ../foo.jpg
+pub fn extract_zip(path: &Path, dir: &Path) -> ZipResult<Vec<PathBuf>> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+    let mut files = vec![];
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let name = match entry.enclosed_name() {
+            Some(name) if entry.is_file() => name,
+            _ => continue,
+        };
+        let target = dir.join(&name);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut entry, &mut File::create(&target)?)?;
+        files.push(target);
+    }
+    Ok(files)
+}
+
+fn hash_files(dir: &Path) -> io::Result<HashMap<String, PathBuf>> {
+    let mut files = HashMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        // 跳过 galleryinfo.txt 等非图片文件
+        if !path.is_file() || path.extension().is_none_or(|ext| ext == "txt") {
+            continue;
+        }
+        files.insert(hash_file(&path)?, path);
+    }
+    Ok(files)
+}
+
+/// 计算文件的 hash，和 E 站一样使用 sha1 的前 10 位
+fn hash_file(path: &Path) -> io::Result<String> {
+    let digest = Sha1::digest(fs::read(path)?);
+    Ok(digest.iter().take(5).map(|b| format!("{:02x}", b)).collect())
+}
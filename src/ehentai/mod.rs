@@ -1,7 +1,9 @@
 mod client;
 mod error;
+mod local;
 mod types;
 
 pub use client::*;
 pub use error::*;
+pub use local::*;
 pub use types::*;
@@ -5,6 +5,7 @@ use chrono::prelude::*;
 use indexmap::IndexMap;
 use once_cell::sync::Lazy;
 use regex::Regex;
+use serde::Deserialize;
 
 use super::error::EhError;
 use crate::database::GalleryEntity;
@@ -155,6 +156,8 @@ pub struct EhGallery {
     pub posted: NaiveDateTime,
     /// 封面是第几张
     pub cover: usize,
+    /// 上传者，已注销的用户或者从本地导入时可能为空
+    pub uploader: Option<String>,
 }
 
 pub trait GalleryInfo {
@@ -169,6 +172,10 @@ pub trait GalleryInfo {
     fn pages(&self) -> usize;
 
     fn cover(&self) -> usize;
+
+    fn favorite(&self) -> Option<i32>;
+
+    fn posted(&self) -> Option<NaiveDateTime>;
 }
 
 impl GalleryInfo for EhGallery {
@@ -195,6 +202,14 @@ impl GalleryInfo for EhGallery {
     fn cover(&self) -> usize {
         self.cover
     }
+
+    fn favorite(&self) -> Option<i32> {
+        Some(self.favorite)
+    }
+
+    fn posted(&self) -> Option<NaiveDateTime> {
+        Some(self.posted)
+    }
 }
 
 impl GalleryInfo for GalleryEntity {
@@ -221,6 +236,133 @@ impl GalleryInfo for GalleryEntity {
     fn cover(&self) -> usize {
         0
     }
+
+    fn favorite(&self) -> Option<i32> {
+        self.favorite
+    }
+
+    fn posted(&self) -> Option<NaiveDateTime> {
+        self.posted
+    }
+}
+
+/// H@H 下载的分辨率
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub enum ArchiveResolution {
+    X780,
+    X980,
+    X1280,
+    X1600,
+    X2400,
+    #[default]
+    Original,
+}
+
+impl ArchiveResolution {
+    /// 请求归档时提交的参数
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::X780 => "780",
+            Self::X980 => "980",
+            Self::X1280 => "1280",
+            Self::X1600 => "1600",
+            Self::X2400 => "2400",
+            Self::Original => "org",
+        }
+    }
+}
+
+impl FromStr for ArchiveResolution {
+    type Err = EhError;
+
+    /// 支持 780、780x、org、original 等写法
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().trim_end_matches(['x', 'X']).to_lowercase().as_str() {
+            "780" => Ok(Self::X780),
+            "980" => Ok(Self::X980),
+            "1280" => Ok(Self::X1280),
+            "1600" => Ok(Self::X1600),
+            "2400" => Ok(Self::X2400),
+            "org" | "original" => Ok(Self::Original),
+            _ => Err(EhError::InvalidResolution(s.to_owned())),
+        }
+    }
+}
+
+impl TryFrom<String> for ArchiveResolution {
+    type Error = EhError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Display for ArchiveResolution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 直接下载的归档类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveKind {
+    /// 原图归档
+    #[default]
+    Original,
+    /// 重新采样的归档，分辨率取决于 E 站的设置
+    Resample,
+}
+
+impl ArchiveKind {
+    /// 请求下载时提交的 dltype 参数
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Original => "org",
+            Self::Resample => "res",
+        }
+    }
+
+    /// 请求下载时提交的 dlcheck 参数，即页面上按钮的文字
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Original => "Download Original Archive",
+            Self::Resample => "Download Resample Archive",
+        }
+    }
+}
+
+/// 归档页面上某个分辨率的下载选项
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveOption {
+    pub resolution: ArchiveResolution,
+    /// 文件大小，例如 136.1 MiB，不可用时为空
+    pub size: Option<String>,
+    /// 所需的 GP，不可用时为空
+    pub cost: Option<u64>,
+}
+
+impl ArchiveOption {
+    /// 解析归档页面上显示的花费，例如 Free、1,145 GP
+    pub fn parse_cost(s: &str) -> Option<u64> {
+        let s = s.trim();
+        if s.starts_with("Free") {
+            return Some(0);
+        }
+        s.strip_suffix("GP")?.trim().replace(',', "").parse().ok()
+    }
+}
+
+/// 请求归档的结果
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArchiveResult {
+    /// 已经请求 H@H 下载，cost 为花费的 GP
+    Requested { resolution: ArchiveResolution, cost: u64 },
+    /// 该分辨率不可用
+    Unavailable { resolution: ArchiveResolution },
+    /// 所需的 GP 超出了预算，没有发出请求
+    OverBudget { resolution: ArchiveResolution, cost: u64, budget: u64 },
 }
 
 #[cfg(test)]
@@ -245,4 +387,14 @@ mod tests {
         assert_eq!(url.page, 1);
         assert_eq!(url.url(), s);
     }
+
+    #[test]
+    fn parse_archive() {
+        assert_eq!("780x".parse::<ArchiveResolution>().unwrap(), ArchiveResolution::X780);
+        assert_eq!("Original".parse::<ArchiveResolution>().unwrap(), ArchiveResolution::Original);
+        assert!("1080".parse::<ArchiveResolution>().is_err());
+        assert_eq!(ArchiveOption::parse_cost("Free!"), Some(0));
+        assert_eq!(ArchiveOption::parse_cost("1,145 GP"), Some(1145));
+        assert_eq!(ArchiveOption::parse_cost("N/A"), None);
+    }
 }
@@ -0,0 +1,209 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use chrono::{NaiveDateTime, Utc};
+use indexmap::IndexMap;
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use crate::ehentai::{extract_zip, EhGallery, EhGalleryUrl, EhPageUrl};
+use crate::utils::natural_cmp;
+
+/// 可以导入的图片格式
+const IMAGE_EXTS: &[&str] = &["jpg", "jpeg", "png", "gif", "webp"];
+/// 能够识别但是上传时会被跳过的图片格式，导入时直接报错，避免页面缺失
+const UNSUPPORTED_EXTS: &[&str] = &["gif"];
+/// 文件夹或压缩包中的元数据文件名
+const SIDECAR_NAME: &str = "info.json";
+
+/// 导入的来源，可以是文件夹或者 ZIP/CBZ 文件
+///
+/// 压缩包会先解压到临时目录，并在导入结束后删除
+#[derive(Debug)]
+pub struct ImportSource {
+    /// 图片所在的目录
+    dir: PathBuf,
+    /// 按自然顺序排列的图片
+    files: Vec<PathBuf>,
+    /// 元数据文件
+    sidecar: Option<PathBuf>,
+    /// 是否为解压出来的临时目录
+    temporary: bool,
+}
+
+impl ImportSource {
+    pub fn open(path: &Path) -> Result<Self> {
+        if path.is_dir() {
+            let files = list_images(path)?;
+            let sidecar = Some(path.join(SIDECAR_NAME)).filter(|p| p.is_file());
+            return Self::new(path.to_owned(), files, sidecar, false);
+        }
+
+        let ext = path.extension().and_then(|s| s.to_str()).unwrap_or_default().to_lowercase();
+        if !matches!(ext.as_str(), "zip" | "cbz") {
+            bail!("只支持导入文件夹、ZIP 或 CBZ 文件：{}", path.display());
+        }
+        let dir = std::env::temp_dir().join(format!("exloli-import-{}", std::process::id()));
+        let (files, sidecar) = extract(path, &dir)
+            .with_context(|| format!("解压失败：{}", path.display()))
+            .inspect_err(|_| {
+                let _ = fs::remove_dir_all(&dir);
+            })?;
+        // 压缩包内没有元数据时，使用同名的 JSON 文件，例如 foo.zip 对应 foo.json
+        let sidecar = sidecar.or_else(|| Some(path.with_extension("json")).filter(|p| p.is_file()));
+        Self::new(dir, files, sidecar, true)
+    }
+
+    fn new(
+        dir: PathBuf,
+        files: Vec<PathBuf>,
+        sidecar: Option<PathBuf>,
+        temporary: bool,
+    ) -> Result<Self> {
+        let source = Self { dir, files, sidecar, temporary };
+        if source.files.is_empty() {
+            bail!("没有找到可以导入的图片");
+        }
+        let unsupported = source
+            .files
+            .iter()
+            .filter(|p| has_ext(p, UNSUPPORTED_EXTS))
+            .map(|p| p.file_name().unwrap_or_default().to_string_lossy())
+            .collect::<Vec<_>>();
+        if !unsupported.is_empty() {
+            bail!("不支持导入以下格式的图片，请先转换为 JPG 或 PNG：{}", unsupported.join(", "));
+        }
+        debug!("导入图片数量：{}", source.files.len());
+        Ok(source)
+    }
+
+    /// 按自然顺序排列的图片
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// 文件夹或压缩包中的 info.json，或者压缩包旁边的同名 JSON 文件
+    pub fn sidecar(&self) -> Option<&Path> {
+        self.sidecar.as_deref()
+    }
+}
+
+impl Drop for ImportSource {
+    fn drop(&mut self) {
+        if self.temporary {
+            if let Err(err) = fs::remove_dir_all(&self.dir) {
+                warn!("删除临时目录失败：{} {}", self.dir.display(), err);
+            }
+        }
+    }
+}
+
+/// JSON 格式的画廊元数据，字段和 EhGallery 对应
+///
+/// ```json
+/// {
+///     "url": "https://exhentai.org/g/2549143/16b1b7bab0/",
+///     "title": "...",
+///     "title_jp": "...",
+///     "tags": { "female": ["lolicon"] },
+///     "posted": "2023-06-17 01:00"
+/// }
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct Sidecar {
+    /// 画廊在 E 站的地址，用于确定画廊 ID
+    ///
+    /// 画廊以 E 站的 ID 和 token 保存在数据库中，因此只存在于本地的素材也需要指定一个地址
+    pub url: Option<String>,
+    pub title: String,
+    pub title_jp: Option<String>,
+    #[serde(default)]
+    pub tags: IndexMap<String, Vec<String>>,
+    #[serde(default)]
+    pub favorite: i32,
+    /// 父画廊地址
+    pub parent: Option<String>,
+    /// 发布时间，格式和 E 站相同，例如 2023-06-17 01:00，为空时使用当前时间
+    pub posted: Option<String>,
+    /// 上传者
+    pub uploader: Option<String>,
+}
+
+impl Sidecar {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("无法读取元数据文件：{}", path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("元数据文件格式错误：{}", path.display()))
+    }
+
+    /// 转换为画廊信息，页面需要在之后根据导入的图片填写
+    pub fn into_gallery(self) -> Result<EhGallery> {
+        let posted = match &self.posted {
+            Some(posted) => NaiveDateTime::parse_from_str(posted, "%Y-%m-%d %H:%M")?,
+            None => Utc::now().naive_utc(),
+        };
+        let url = self.url.context(
+            "元数据缺少 url 字段：画廊以 E 站的 ID 和 token 保存，请填写对应的 E 站画廊地址",
+        )?;
+        let url = url.parse().with_context(|| format!("无效的 E 站画廊地址：{url}"))?;
+        Ok(EhGallery {
+            url,
+            title: self.title,
+            title_jp: self.title_jp,
+            tags: self.tags,
+            favorite: self.favorite,
+            parent: self.parent.map(|s| s.parse()).transpose()?,
+            pages: vec![],
+            posted,
+            cover: 0,
+            uploader: self.uploader,
+        })
+    }
+}
+
+/// 根据导入的图片的 hash 生成画廊页面
+pub fn pages_of(url: &EhGalleryUrl, hashes: &[String]) -> Result<Vec<EhPageUrl>> {
+    let mut pages = vec![];
+    for (i, hash) in hashes.iter().enumerate() {
+        let page = format!("https://exhentai.org/s/{}/{}-{}", hash, url.id(), i + 1);
+        pages.push(page.parse()?);
+    }
+    Ok(pages)
+}
+
+fn is_image(path: &Path) -> bool {
+    has_ext(path, IMAGE_EXTS)
+}
+
+fn has_ext(path: &Path, exts: &[&str]) -> bool {
+    let ext = path.extension().and_then(|s| s.to_str()).unwrap_or_default();
+    exts.contains(&ext.to_lowercase().as_str())
+}
+
+fn sort_naturally(files: &mut [PathBuf]) {
+    files.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+}
+
+fn list_images(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && is_image(&path) {
+            files.push(path);
+        }
+    }
+    sort_naturally(&mut files);
+    Ok(files)
+}
+
+/// 将压缩包中的图片和元数据解压到指定目录，返回图片和元数据的路径
+fn extract(path: &Path, dir: &Path) -> Result<(Vec<PathBuf>, Option<PathBuf>)> {
+    let files = extract_zip(path, dir)?;
+    let sidecar = files.iter().find(|p| p.file_name().is_some_and(|s| s == SIDECAR_NAME)).cloned();
+    let mut files = files.into_iter().filter(|p| is_image(p)).collect::<Vec<_>>();
+    sort_naturally(&mut files);
+    Ok((files, sidecar))
+}
@@ -2,7 +2,10 @@ pub mod bot;
 pub mod config;
 pub mod database;
 pub mod ehentai;
+pub mod import;
+pub mod rules;
 mod catbox;
 pub mod tags;
+pub mod template;
 pub mod uploader;
 pub mod utils;
@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use serde::Deserialize;
+
+use crate::ehentai::EhGallery;
+
+/// 没有 language tag 的画廊默认为日语
+const DEFAULT_LANGUAGE: &str = "japanese";
+/// language 中不表示语言的 tag
+const LANGUAGE_MARKERS: &[&str] = &["translated", "rewrite", "speechless", "text cleaned"];
+
+/// 自动上传前对画廊进行检查的规则，所有条件都满足时才会上传
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct UploadRules {
+    /// 必须包含的 tag，键为 namespace，每个 namespace 中至少要包含其中一个
+    pub require: HashMap<String, Vec<String>>,
+    /// 不能包含的 tag，键为 namespace，包含其中任意一个都不会上传
+    pub exclude: HashMap<String, Vec<String>>,
+    /// 最少收藏数量
+    pub min_favorite: Option<i32>,
+    /// 最少页数
+    pub min_pages: Option<usize>,
+    /// 最多页数
+    pub max_pages: Option<usize>,
+    /// 允许的语言，为空则不限制，没有 language tag 的画廊视为 japanese
+    pub languages: Vec<String>,
+    /// 不上传这些用户上传的画廊
+    pub uploader_blacklist: Vec<String>,
+}
+
+/// 画廊没有通过检查的原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rejection {
+    /// 缺少某个 namespace 中要求的 tag
+    MissingTag(String),
+    /// 包含不允许的 tag
+    ExcludedTag(String, String),
+    /// 收藏数量不足
+    Favorite(i32, i32),
+    /// 页数不足
+    TooFewPages(usize, usize),
+    /// 页数过多
+    TooManyPages(usize, usize),
+    /// 语言不在允许的范围内
+    Language(Vec<String>),
+    /// 上传者在黑名单中
+    Uploader(String),
+}
+
+impl UploadRules {
+    /// 检查画廊是否满足所有规则，返回第一条不满足的规则
+    pub fn check(&self, gallery: &EhGallery) -> Result<(), Rejection> {
+        for (namespace, required) in &self.require {
+            if !required.iter().any(|tag| has_tag(gallery, namespace, tag)) {
+                return Err(Rejection::MissingTag(namespace.clone()));
+            }
+        }
+        for (namespace, excluded) in &self.exclude {
+            if let Some(tag) = excluded.iter().find(|tag| has_tag(gallery, namespace, tag)) {
+                return Err(Rejection::ExcludedTag(namespace.clone(), tag.clone()));
+            }
+        }
+
+        if let Some(min) = self.min_favorite.filter(|min| gallery.favorite < *min) {
+            return Err(Rejection::Favorite(gallery.favorite, min));
+        }
+        let pages = gallery.pages.len();
+        if let Some(min) = self.min_pages.filter(|min| pages < *min) {
+            return Err(Rejection::TooFewPages(pages, min));
+        }
+        if let Some(max) = self.max_pages.filter(|max| pages > *max) {
+            return Err(Rejection::TooManyPages(pages, max));
+        }
+
+        if !self.languages.is_empty() {
+            let languages = languages_of(gallery);
+            if !languages.iter().any(|lang| self.languages.iter().any(|l| l == lang)) {
+                return Err(Rejection::Language(languages));
+            }
+        }
+        if let Some(uploader) = &gallery.uploader {
+            if self.uploader_blacklist.iter().any(|u| u.eq_ignore_ascii_case(uploader)) {
+                return Err(Rejection::Uploader(uploader.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// 检查规则本身是否合法，返回所有问题
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = vec![];
+        for (namespace, tags) in self.require.iter().chain(&self.exclude) {
+            if tags.is_empty() {
+                errors.push(format!("rules 中 {namespace} 的 tag 列表不能为空"));
+            }
+        }
+        if let (Some(min), Some(max)) = (self.min_pages, self.max_pages) {
+            if min > max {
+                errors.push(format!("rules.min_pages {min} 不能大于 rules.max_pages {max}"));
+            }
+        }
+        errors
+    }
+}
+
+impl Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingTag(ns) => write!(f, "rules.require：缺少 {ns} 中要求的 tag"),
+            Self::ExcludedTag(ns, tag) => write!(f, "rules.exclude：包含 {ns}:{tag}"),
+            Self::Favorite(n, min) => write!(f, "rules.min_favorite：收藏数 {n} 少于 {min}"),
+            Self::TooFewPages(n, min) => write!(f, "rules.min_pages：页数 {n} 少于 {min}"),
+            Self::TooManyPages(n, max) => write!(f, "rules.max_pages：页数 {n} 多于 {max}"),
+            Self::Language(langs) => write!(f, "rules.languages：语言为 {}", langs.join("、")),
+            Self::Uploader(name) => write!(f, "rules.uploader_blacklist：上传者为 {name}"),
+        }
+    }
+}
+
+fn has_tag(gallery: &EhGallery, namespace: &str, tag: &str) -> bool {
+    // NOTE: 形如 nekogen | miyauchi takeshi 的 tag，任意一个名称匹配即可
+    gallery
+        .tags
+        .get(namespace)
+        .is_some_and(|tags| tags.iter().any(|t| t.split(" | ").any(|t| t == tag)))
+}
+
+/// 获取画廊的语言，会忽略 translated 等标记
+fn languages_of(gallery: &EhGallery) -> Vec<String> {
+    let languages = gallery
+        .tags
+        .get("language")
+        .map(|tags| tags.iter().filter(|t| !LANGUAGE_MARKERS.contains(&t.as_str())).cloned())
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    if languages.is_empty() {
+        vec![DEFAULT_LANGUAGE.to_string()]
+    } else {
+        languages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+    use indexmap::IndexMap;
+
+    use super::*;
+
+    fn gallery(tags: &[(&str, &[&str])], pages: usize) -> EhGallery {
+        let tags = tags
+            .iter()
+            .map(|(ns, tags)| (ns.to_string(), tags.iter().map(|t| t.to_string()).collect()))
+            .collect::<IndexMap<_, _>>();
+        let pages = (1..=pages)
+            .map(|i| format!("https://exhentai.org/s/03af734602/1932743-{i}").parse().unwrap())
+            .collect();
+        EhGallery {
+            url: "https://exhentai.org/g/1932743/3962191348/".parse().unwrap(),
+            title: "test".to_string(),
+            title_jp: None,
+            tags,
+            favorite: 100,
+            parent: None,
+            pages,
+            posted: NaiveDateTime::default(),
+            cover: 0,
+            uploader: Some("someone".to_string()),
+        }
+    }
+
+    #[test]
+    fn check_rules() {
+        let rules: UploadRules = toml::from_str(
+            r#"
+            require = { female = ["lolicon"] }
+            exclude = { other = ["ai generated"] }
+            min_favorite = 50
+            max_pages = 10
+            languages = ["chinese"]
+            uploader_blacklist = ["SomeOne"]
+            "#,
+        )
+        .unwrap();
+        let tags: &[(&str, &[&str])] =
+            &[("language", &["chinese", "translated"]), ("female", &["lolicon"])];
+
+        let mut g = gallery(tags, 5);
+        assert_eq!(rules.check(&g), Err(Rejection::Uploader("someone".to_string())));
+        g.uploader = None;
+        assert_eq!(rules.check(&g), Ok(()));
+        g.favorite = 10;
+        assert_eq!(rules.check(&g), Err(Rejection::Favorite(10, 50)));
+
+        let g = gallery(&[("female", &["lolicon"])], 5);
+        assert_eq!(rules.check(&g), Err(Rejection::Language(vec!["japanese".to_string()])));
+        let g = gallery(&[("female", &["lolicon"]), ("other", &["ai generated"])], 5);
+        let excluded = Rejection::ExcludedTag("other".to_string(), "ai generated".to_string());
+        assert_eq!(rules.check(&g), Err(excluded));
+        assert_eq!(rules.check(&gallery(tags, 20)), Err(Rejection::TooManyPages(20, 10)));
+        let missing = Rejection::MissingTag("female".to_string());
+        assert_eq!(rules.check(&gallery(&tags[..1], 5)), Err(missing));
+        assert_eq!(UploadRules::default().check(&gallery(&[], 0)), Ok(()));
+    }
+}
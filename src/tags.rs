@@ -1,20 +1,82 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::sync::{Arc, RwLock};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use indexmap::IndexMap;
-use serde::Deserialize;
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
 use tokio::time::{self, Duration};
-use tracing::{error, info};
+use tracing::{debug, error, info, warn};
 
 #[derive(Debug, Clone)]
 pub struct EhTagTransDB {
     file: String,
-    db: Arc<RwLock<Option<EhTagTransDBInner>>>,
+    db: Arc<RwLock<TagIndex>>,
+    /// 本地翻译覆盖文件的位置
+    override_file: Option<String>,
+    overrides: Arc<RwLock<TagOverrides>>,
 }
 
-#[derive(Debug, Deserialize)]
+/// 本地的翻译覆盖，优先于翻译数据库，更新翻译数据库时不会受到影响
+///
+/// ```toml
+/// # 不在消息中显示的 tag，也可以隐藏整个 namespace
+/// hidden = ["other:extraneous ads", "reclass"]
+///
+/// # 修改或者补充翻译，namespace 的翻译写作 rows:namespace
+/// [rename]
+/// "female:lolicon" = "萝莉"
+/// "rows:female" = "女"
+///
+/// # 将一个 tag 视为另一个 tag，两者同时出现时会被合并
+/// [alias]
+/// "female:loli" = "female:lolicon"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+struct OverrideFile {
+    #[serde(default)]
+    hidden: Vec<String>,
+    #[serde(default)]
+    rename: HashMap<String, String>,
+    #[serde(default)]
+    alias: HashMap<String, String>,
+}
+
+/// 解析后的本地翻译覆盖，按 namespace 和 tag 索引
+#[derive(Debug, Default)]
+struct TagOverrides {
+    /// 整个隐藏的 namespace
+    hidden_namespaces: HashSet<String>,
+    /// namespace -> 隐藏的 tag
+    hidden: HashMap<String, HashSet<String>>,
+    rename: TagIndex,
+    /// namespace -> tag -> 指向的 namespace 和 tag
+    alias: HashMap<String, HashMap<String, (String, String)>>,
+}
+
+/// 按 namespace 和 tag 索引的翻译，以及从翻译后的名称到 tag 的反向索引
+#[derive(Debug, Default)]
+struct TagIndex {
+    /// 按照翻译数据库中的顺序排列的 namespace
+    namespaces: Vec<String>,
+    /// namespace -> tag -> 翻译
+    tags: HashMap<String, HashMap<String, TagInfo>>,
+    /// 翻译后的名称 -> namespace 和 tag，形如 a | b 的名称中的每一个都会被索引
+    names: HashMap<String, Vec<(String, String)>>,
+}
+
+/// 当前翻译数据库对应的版本，保存在翻译文件旁边，用于判断是否需要更新
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct VersionInfo {
+    /// release 的 tag
+    tag: String,
+    /// 获取 release 信息时返回的 ETag
+    etag: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
 struct EhTagTransDBInner {
     // repo: String,
     // head: Value,
@@ -33,15 +95,64 @@ struct EhTagTransData {
 #[derive(Debug, Deserialize)]
 struct TagInfo {
     name: String,
-    // intro: String,
-    // links: String,
+    #[serde(default)]
+    intro: String,
+    #[serde(default)]
+    links: String,
+}
+
+/// tag 的翻译和介绍
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagDetail {
+    pub namespace: String,
+    pub tag: String,
+    /// 翻译后的名称，多个名称之间用 | 分隔，没有翻译时为空
+    pub name: String,
+    /// 介绍
+    pub intro: String,
+    /// 外部链接
+    pub links: String,
 }
 
 impl EhTagTransDB {
+    /// 读取翻译数据库，文件不存在或者无法解析时，使用空的数据库，等待之后自动更新
     pub fn new(file: &str) -> Self {
-        let text = fs::read_to_string(file).expect("无法打开 db.text.json");
-        let db = serde_json::from_str(&text).expect("无法解析翻译数据库");
-        Self { file: file.to_string(), db: Arc::new(RwLock::new(Some(db))) }
+        let db = match fs::read_to_string(file) {
+            Ok(text) => match serde_json::from_str::<EhTagTransDBInner>(&text) {
+                Ok(db) => db.into(),
+                Err(err) => {
+                    error!("无法解析翻译数据库 {}：{}，暂时不翻译 tag", file, err);
+                    Default::default()
+                }
+            },
+            Err(err) => {
+                warn!("无法打开翻译数据库 {}：{}，暂时不翻译 tag", file, err);
+                Default::default()
+            }
+        };
+        Self {
+            file: file.to_string(),
+            db: Arc::new(RwLock::new(db)),
+            override_file: None,
+            overrides: Default::default(),
+        }
+    }
+
+    /// 使用本地翻译覆盖文件，文件无法读取时暂时不使用覆盖
+    pub fn with_overrides(mut self, file: &str) -> Self {
+        self.override_file = Some(file.to_string());
+        if let Err(err) = self.reload_overrides() {
+            error!("{:#}", err);
+        }
+        self
+    }
+
+    /// 重新读取本地翻译覆盖文件，读取失败时继续使用旧的覆盖
+    pub fn reload_overrides(&self) -> Result<()> {
+        let Some(file) = &self.override_file else { return Ok(()) };
+        let overrides = TagOverrides::load(file)?;
+        *self.overrides.write().unwrap() = overrides;
+        Ok(())
     }
 
     pub async fn start(&self) {
@@ -58,12 +169,29 @@ impl EhTagTransDB {
         info!("更新 tag 中……");
         // 此处得设置 user-agent，否则 github 会 403
         let client = reqwest::Client::builder().user_agent("exloli").build()?;
-        let resp = client
-            .get("https://api.github.com/repos/EhTagTranslation/Database/releases/latest")
-            .send()
-            .await?
-            .error_for_status()?;
+        let version_file = format!("{}.version", self.file);
+        let version = self.local_version();
+
+        // 带上 ETag 请求，没有变化时 GitHub 会返回 304，并且不计入请求次数限制
+        let mut req =
+            client.get("https://api.github.com/repos/EhTagTranslation/Database/releases/latest");
+        if let Some(etag) = &version.etag {
+            req = req.header(IF_NONE_MATCH, etag);
+        }
+        let resp = req.send().await?.error_for_status()?;
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            info!("翻译数据库没有更新：{}", version.tag);
+            return Ok(());
+        }
+        let etag = resp.headers().get(ETAG).and_then(|v| v.to_str().ok()).map(String::from);
         let json = resp.json::<serde_json::Value>().await?;
+        let tag = json.get("tag_name").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+        let new_version = VersionInfo { tag, etag };
+        if !version.tag.is_empty() && version.tag == new_version.tag {
+            info!("翻译数据库没有更新：{}", version.tag);
+            write_atomic(&version_file, &serde_json::to_string(&new_version)?)?;
+            return Ok(());
+        }
 
         let extract = |v: &serde_json::Value| -> Option<String> {
             for v in v.get("assets")?.as_array()? {
@@ -75,29 +203,98 @@ impl EhTagTransDB {
         };
         let url = extract(&json).context("找不到 URL")?;
 
-        // NOTE: 其实可以检测一下再决定是否需要更新，但是这么点东西懒得检测了
         let resp = client.get(url).send().await?.error_for_status()?;
         let text = resp.text().await?;
-        fs::write(&self.file, &text)?;
-
-        let db = serde_json::from_str(&text)?;
-        let mut lock = self.db.write().unwrap();
-        lock.replace(db);
+        // 先解析，解析失败时保留旧的数据库和文件
+        let db = serde_json::from_str::<EhTagTransDBInner>(&text)
+            .context("无法解析新的翻译数据库，继续使用旧的数据库")?;
+        write_atomic(&self.file, &text)?;
+        write_atomic(&version_file, &serde_json::to_string(&new_version)?)?;
+        *self.db.write().unwrap() = db.into();
+        info!("翻译数据库已更新：{} -> {}", version.tag, new_version.tag);
 
         Ok(())
     }
 
+    /// 读取本地翻译数据库的版本
+    ///
+    /// 翻译文件不存在或者无法解析时（例如写入到一半被中断），即使版本相同也需要重新下载，
+    /// 因此返回空的版本
+    fn local_version(&self) -> VersionInfo {
+        let valid = fs::read_to_string(&self.file)
+            .is_ok_and(|text| serde_json::from_str::<EhTagTransDBInner>(&text).is_ok());
+        if !valid {
+            warn!("翻译数据库不存在或者已损坏，需要重新下载：{}", self.file);
+            return VersionInfo::default();
+        }
+        fs::read_to_string(format!("{}.version", self.file))
+            .ok()
+            .and_then(|s| serde_json::from_str::<VersionInfo>(&s).ok())
+            .unwrap_or_default()
+    }
+
     /// 返回不经过任何修改的翻译结果，即多个结果之间用 | 分隔
     pub fn trans_raw(&self, namespace: &str, name: &str) -> String {
         // NOTE: 对于形如 nekogen | miyauchi takeshi 的 tag，只需要取第一部分翻译
         let name = name.split(" | ").next().unwrap();
-        let lock = self.db.read().unwrap();
-        for ns in &lock.as_ref().unwrap().data {
-            if ns.namespace == namespace {
-                return ns.data.get(name).map(|info| info.name.as_str()).unwrap_or(name).to_owned();
+        let overrides = self.overrides.read().unwrap();
+        let db = self.db.read().unwrap();
+        let (namespace, name) = overrides.resolve(namespace, name);
+        name_of(&overrides, &db, namespace, name).unwrap_or(name).to_owned()
+    }
+
+    /// 获取指定 tag 的翻译和介绍，没有翻译时返回 None
+    pub fn detail(&self, namespace: &str, tag: &str) -> Option<TagDetail> {
+        let overrides = self.overrides.read().unwrap();
+        let db = self.db.read().unwrap();
+        let (namespace, tag) = overrides.resolve(namespace, tag);
+        let name = name_of(&overrides, &db, namespace, tag)?;
+        let info = db.get(namespace, tag);
+        Some(TagDetail {
+            namespace: namespace.to_owned(),
+            tag: tag.to_owned(),
+            name: name.to_owned(),
+            intro: info.map(|info| info.intro.clone()).unwrap_or_default(),
+            links: info.map(|info| info.links.clone()).unwrap_or_default(),
+        })
+    }
+
+    /// 查找 tag，支持 namespace:tag 或者只有 tag，namespace 和 tag 都可以使用英文名或者翻译后的名称
+    pub fn search(&self, query: &str) -> Vec<TagDetail> {
+        let (namespace, tag) = match query.split_once(':').or_else(|| query.split_once('：')) {
+            Some((ns, tag)) => (Some(ns.trim()), tag.trim()),
+            None => (None, query.trim()),
+        };
+        let mut found = vec![];
+        {
+            let overrides = self.overrides.read().unwrap();
+            let db = self.db.read().unwrap();
+            let by_name = |name| overrides.rename.find(name).chain(db.find(name));
+            // namespace 是翻译后的名称时，先找到对应的英文名
+            let namespace = namespace.map(|q| {
+                by_name(q).find(|(ns, _)| ns == "rows").map(|(_, ns)| ns.as_str()).unwrap_or(q)
+            });
+            let namespaces = db.namespaces.iter().chain(&overrides.rename.namespaces);
+            let by_tag = namespaces
+                .chain(overrides.alias.keys())
+                .filter(|ns| {
+                    db.get(ns, tag).is_some()
+                        || overrides.rename.get(ns, tag).is_some()
+                        || overrides.alias.get(*ns).is_some_and(|tags| tags.contains_key(tag))
+                })
+                .map(|ns| (ns.as_str(), tag));
+            let by_name = by_name(tag).map(|(ns, tag)| (ns.as_str(), tag.as_str()));
+            for (ns, tag) in by_tag.chain(by_name) {
+                if ns == "rows" || namespace.is_some_and(|q| q != ns) {
+                    continue;
+                }
+                let (ns, tag) = overrides.resolve(ns, tag);
+                if !found.iter().any(|(n, t)| n == ns && t == tag) {
+                    found.push((ns.to_owned(), tag.to_owned()));
+                }
             }
         }
-        name.to_owned()
+        found.iter().filter_map(|(ns, tag)| self.detail(ns, tag)).collect()
     }
 
     /// 根据 namespace 和 tag name 进行翻译
@@ -112,11 +309,12 @@ impl EhTagTransDB {
         self.trans("rows", namespace).swap_remove(0)
     }
 
-    /// 翻译整组 tags
+    /// 翻译整组 tags，会去掉隐藏的 tag，并合并互为别名的 tag
     pub fn trans_tags(
         &self,
         tags: &IndexMap<String, Vec<String>>,
     ) -> IndexMap<String, Vec<String>> {
+        let tags = self.resolve_tags(tags);
         let mut result = IndexMap::new();
         for (namespace, tags) in tags.iter() {
             let t_ns = self.trans_namespace(namespace);
@@ -125,6 +323,153 @@ impl EhTagTransDB {
         }
         result
     }
+
+    /// 找出没有翻译的 tag，隐藏的 tag 不会被列出
+    pub fn untranslated(&self, tags: &IndexMap<String, Vec<String>>) -> Vec<(String, String)> {
+        let tags = self.resolve_tags(tags);
+        let overrides = self.overrides.read().unwrap();
+        let db = self.db.read().unwrap();
+        let mut result = vec![];
+        for (namespace, tags) in tags {
+            for tag in tags {
+                if name_of(&overrides, &db, &namespace, &tag).is_none() {
+                    result.push((namespace.clone(), tag));
+                }
+            }
+        }
+        result
+    }
+
+    /// 将别名替换为其指向的 tag，并去掉隐藏和重复的 tag，别名指向的 tag 可能在其他 namespace 中
+    pub fn resolve_tags(
+        &self,
+        tags: &IndexMap<String, Vec<String>>,
+    ) -> IndexMap<String, Vec<String>> {
+        let overrides = self.overrides.read().unwrap();
+        let mut result = IndexMap::<String, Vec<String>>::new();
+        for (namespace, tags) in tags.iter() {
+            for tag in tags {
+                let tag = tag.split(" | ").next().unwrap();
+                let (ns, t) = overrides.resolve(namespace, tag);
+                if overrides.is_hidden(namespace, tag) || overrides.is_hidden(ns, t) {
+                    continue;
+                }
+                let entry = result.entry(ns.to_owned()).or_default();
+                if !entry.iter().any(|x| x == t) {
+                    entry.push(t.to_owned());
+                }
+            }
+        }
+        result
+    }
+}
+
+impl TagOverrides {
+    fn load(file: &str) -> Result<Self> {
+        let text =
+            fs::read_to_string(file).with_context(|| format!("无法读取翻译覆盖文件 {}", file))?;
+        let raw: OverrideFile =
+            toml::from_str(&text).with_context(|| format!("无法解析翻译覆盖文件 {}", file))?;
+        info!(
+            "已读取翻译覆盖：隐藏 {} 个，修改 {} 个，别名 {} 个",
+            raw.hidden.len(),
+            raw.rename.len(),
+            raw.alias.len()
+        );
+
+        let split = |key: &str| match key.split_once(':') {
+            Some((ns, tag)) => Ok((ns.to_owned(), tag.to_owned())),
+            None => Err(anyhow!("翻译覆盖文件中的 {} 应当形如 namespace:tag", key)),
+        };
+        let mut overrides = Self::default();
+        for key in raw.hidden {
+            match key.split_once(':') {
+                Some((ns, tag)) => {
+                    overrides.hidden.entry(ns.to_owned()).or_default().insert(tag.to_owned());
+                }
+                None => {
+                    overrides.hidden_namespaces.insert(key);
+                }
+            }
+        }
+        for (key, name) in raw.rename {
+            let (ns, tag) = split(&key)?;
+            let info = TagInfo { name, intro: String::new(), links: String::new() };
+            overrides.rename.insert(ns, tag, info);
+        }
+        for (key, target) in raw.alias {
+            let (ns, tag) = split(&key)?;
+            overrides.alias.entry(ns).or_default().insert(tag, split(&target)?);
+        }
+        Ok(overrides)
+    }
+
+    /// 如果 tag 是别名，返回其指向的 namespace 和 tag
+    fn resolve<'a>(&'a self, namespace: &'a str, tag: &'a str) -> (&'a str, &'a str) {
+        self.alias
+            .get(namespace)
+            .and_then(|tags| tags.get(tag))
+            .map(|(ns, tag)| (ns.as_str(), tag.as_str()))
+            .unwrap_or((namespace, tag))
+    }
+
+    fn is_hidden(&self, namespace: &str, tag: &str) -> bool {
+        self.hidden_namespaces.contains(namespace)
+            || self.hidden.get(namespace).is_some_and(|tags| tags.contains(tag))
+    }
+}
+
+impl TagIndex {
+    fn insert(&mut self, namespace: String, tag: String, info: TagInfo) {
+        for name in info.name.split(" | ") {
+            self.names.entry(name.to_owned()).or_default().push((namespace.clone(), tag.clone()));
+        }
+        if !self.tags.contains_key(&namespace) {
+            self.namespaces.push(namespace.clone());
+        }
+        self.tags.entry(namespace).or_default().insert(tag, info);
+    }
+
+    fn get(&self, namespace: &str, tag: &str) -> Option<&TagInfo> {
+        self.tags.get(namespace)?.get(tag)
+    }
+
+    /// 根据翻译后的名称查找 tag
+    fn find(&self, name: &str) -> impl Iterator<Item = &(String, String)> {
+        self.names.get(name).into_iter().flatten()
+    }
+}
+
+impl From<EhTagTransDBInner> for TagIndex {
+    fn from(db: EhTagTransDBInner) -> Self {
+        let mut index = Self::default();
+        for ns in db.data {
+            for (tag, info) in ns.data {
+                index.insert(ns.namespace.clone(), tag, info);
+            }
+        }
+        index
+    }
+}
+
+/// 获取 tag 的翻译，本地覆盖优先，namespace 的翻译保存在 rows 中
+fn name_of<'a>(
+    overrides: &'a TagOverrides,
+    db: &'a TagIndex,
+    namespace: &str,
+    tag: &str,
+) -> Option<&'a str> {
+    let info = overrides.rename.get(namespace, tag).or_else(|| db.get(namespace, tag))?;
+    Some(&info.name)
+}
+
+/// 先写入临时文件再重命名，避免写入中途出错时留下不完整的文件
+fn write_atomic(file: &str, text: &str) -> Result<()> {
+    let tmp = format!("{}.tmp", file);
+    fs::write(&tmp, text).with_context(|| format!("无法写入 {}", tmp))?;
+    fs::rename(&tmp, file).with_context(|| format!("无法重命名 {} 为 {}", tmp, file))?;
+    debug!("已写入 {}", file);
+    Ok(())
 }
 
 #[cfg(test)]
@@ -138,4 +483,73 @@ mod test {
         assert_eq!(db.trans("female", "lolicon"), vec!["萝莉"]);
         assert_eq!(db.trans("character", "yui"), vec!["由依", "结衣"]);
     }
+
+    #[test]
+    fn search() {
+        let db = EhTagTransDB::new("./db.text.json");
+        let found = db.search("female:lolicon");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].name, "萝莉");
+        assert_eq!(db.search("女性:萝莉"), found);
+        assert_eq!(db.search("结衣")[0].tag, "yui");
+        assert_eq!(db.search("角色:由依")[0].tag, "yui");
+        assert!(db.search("male:萝莉").is_empty());
+    }
+
+    #[test]
+    fn overrides() {
+        let file = std::env::temp_dir().join("exloli-tag-overrides.toml");
+        let text = r#"
+            hidden = ["language:chinese", "parody"]
+            [rename]
+            "female:lolicon" = "萝莉控"
+            "female:new tag" = "新标签"
+            [alias]
+            "female:loli" = "female:lolicon"
+        "#;
+        std::fs::write(&file, text).unwrap();
+        let db = EhTagTransDB::new("./db.text.json").with_overrides(file.to_str().unwrap());
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(db.trans("female", "lolicon"), vec!["萝莉控"]);
+        assert_eq!(db.trans("female", "loli"), vec!["萝莉控"]);
+        assert_eq!(db.search("female:新标签")[0].tag, "new tag");
+        assert_eq!(db.search("loli")[0].tag, "lolicon");
+
+        let tags = [
+            ("female", vec!["loli", "lolicon", "unknown"]),
+            ("language", vec!["chinese"]),
+            ("parody", vec!["original"]),
+        ]
+        .into_iter()
+        .map(|(ns, tags)| (ns.to_string(), tags.into_iter().map(String::from).collect()))
+        .collect();
+        let result = db.trans_tags(&tags);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result["女性"], vec!["萝莉控", "unknown"]);
+        assert_eq!(db.untranslated(&tags), vec![("female".to_string(), "unknown".to_string())]);
+    }
+
+    #[test]
+    fn corrupt_file() {
+        let file = std::env::temp_dir().join("exloli-corrupt-db.text.json");
+        let file = file.to_str().unwrap();
+        let version = r#"{"tag":"v1","etag":"\"abc\""}"#;
+        std::fs::write(format!("{file}.version"), version).unwrap();
+
+        std::fs::write(file, r#"{"data": []}"#).unwrap();
+        assert_eq!(EhTagTransDB::new(file).local_version().tag, "v1");
+        std::fs::write(file, r#"{"data": ["#).unwrap();
+        assert_eq!(EhTagTransDB::new(file).local_version().tag, "");
+
+        std::fs::remove_file(file).unwrap();
+        std::fs::remove_file(format!("{file}.version")).unwrap();
+    }
+
+    #[test]
+    fn missing_file() {
+        let db = EhTagTransDB::new("./not-exists.json");
+        assert_eq!(db.trans_namespace("female"), "female");
+        assert_eq!(db.trans("female", "lolicon"), vec!["lolicon"]);
+    }
 }
@@ -0,0 +1,121 @@
+use std::fs;
+
+use anyhow::{Context, Result};
+use minijinja::{Environment, Value};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+/// 默认的频道消息模板
+const DEFAULT_MESSAGE: &str = include_str!("../templates/message.html");
+/// 默认的 telegraph 文章模板
+const DEFAULT_ARTICLE: &str = include_str!("../templates/article.html");
+
+/// 模板中可以使用的画廊信息
+#[derive(Debug, Clone, Serialize)]
+pub struct GalleryContext {
+    pub id: i32,
+    /// 画廊在 E 站的地址
+    pub url: String,
+    pub title: String,
+    /// 日文标题，没有时和 title 相同
+    pub title_jp: String,
+    /// 按照显示设置处理过的 tag
+    pub tags: Vec<TagGroup>,
+    pub pages: usize,
+    /// 收藏数量，旧画廊可能没有记录
+    pub favorite: Option<i32>,
+    /// 发布时间，格式和 E 站相同，例如 2023-06-17 01:00
+    pub posted: Option<String>,
+    /// 投票得分，0 ~ 100，尚未发布投票时为空
+    pub score: Option<f32>,
+}
+
+/// 同一个 namespace 下的 tag
+#[derive(Debug, Clone, Serialize)]
+pub struct TagGroup {
+    /// 用于显示的 namespace 名称
+    pub namespace: String,
+    /// 英文原名
+    pub raw: String,
+    /// 用于显示的 tag，已经按照设置转换为 hashtag
+    pub tags: Vec<String>,
+    /// 是否因为数量限制省略了一部分 tag
+    pub more: bool,
+}
+
+/// 渲染频道消息，没有指定模板文件时使用默认模板
+///
+/// 除了画廊信息之外，还可以使用 article_url 和 album_url
+pub fn render_message(
+    file: Option<&str>,
+    gallery: &GalleryContext,
+    article_url: &str,
+    album_url: Option<&str>,
+) -> Result<String> {
+    let ctx = minijinja::context! { article_url, album_url, ..Value::from_serialize(gallery) };
+    render(file, DEFAULT_MESSAGE, ctx)
+}
+
+/// 渲染 telegraph 文章的正文，没有指定模板文件时使用默认模板
+///
+/// 除了画廊信息之外，还可以使用 images 和 cover，即所有图片和封面的地址
+pub fn render_article(
+    file: Option<&str>,
+    gallery: &GalleryContext,
+    images: &[String],
+    cover: Option<&str>,
+) -> Result<String> {
+    let ctx = minijinja::context! { images, cover, ..Value::from_serialize(gallery) };
+    render(file, DEFAULT_ARTICLE, ctx)
+}
+
+/// 检查模板文件能否被正确解析
+pub fn check(file: &str) -> Result<()> {
+    let source = load(Some(file), "")?;
+    environment().template_from_str(&source)?;
+    Ok(())
+}
+
+/// 将 tag 转换为 hashtag，hashtag 中不能包含空格等符号
+pub fn hashtag(tag: &str) -> String {
+    static RE: Lazy<Regex> = Lazy::new(|| Regex::new("[-/· ]").unwrap());
+    format!("#{}", RE.replace_all(tag, "_"))
+}
+
+fn render(file: Option<&str>, default: &str, ctx: Value) -> Result<String> {
+    // 每次渲染时重新读取模板，修改模板后不需要重启
+    let source = load(file, default)?;
+    let env = environment();
+    let template = env.template_from_str(&source)?;
+    Ok(template.render(ctx)?)
+}
+
+fn load(file: Option<&str>, default: &str) -> Result<String> {
+    match file {
+        Some(file) => fs::read_to_string(file).with_context(|| format!("无法读取模板 {}", file)),
+        None => Ok(default.to_string()),
+    }
+}
+
+/// 转义 HTML 中的特殊字符
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn environment() -> Environment<'static> {
+    let mut env = Environment::new();
+    // telegram 消息和 telegraph 文章都是 HTML，变量需要转义，不需要转义时可以使用 safe 过滤器
+    // NOTE: minijinja 自带的转义还会转义 / 等字符，telegram 不一定能识别，因此只转义必要的字符
+    env.set_formatter(|out, _state, value| {
+        if value.is_safe() {
+            out.write_str(&value.to_string())?;
+        } else if !value.is_none() && !value.is_undefined() {
+            out.write_str(&escape(&value.to_string()))?;
+        }
+        Ok(())
+    });
+    env.set_trim_blocks(true);
+    env.add_filter("hashtag", |tag: &str| hashtag(tag));
+    env
+}
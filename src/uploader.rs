@@ -1,47 +1,131 @@
 use std::backtrace::Backtrace;
+use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 
-use anyhow::{anyhow, bail, Result};
-use chrono::{Datelike, Utc};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{Datelike, NaiveDate, Utc};
 use futures::StreamExt;
-use regex::Regex;
+use indexmap::IndexMap;
 use reqwest::{Client, StatusCode};
+use sha1::{Digest, Sha1};
 use telegraph_rs::{html_to_node, Telegraph};
 use teloxide::prelude::*;
 use teloxide::types::MessageId;
+use teloxide::utils::html::escape;
 //use teloxide::utils::html::{code_inline, link};
+use teloxide::{ApiError, RequestError};
+use tokio::sync::{watch, Mutex};
 use tokio::task::JoinHandle;
 use tokio::time;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 use crate::bot::Bot;
 use crate::catbox::CatboxUploader;
-use crate::config::Config;
+use crate::config::{SharedConfig, TagLanguage};
 use crate::database::{
-    GalleryEntity, ImageEntity, MessageEntity, PageEntity, PollEntity, TelegraphEntity,
+    GalleryEntity, ImageEntity, MessageEntity, PageEntity, PollEntity, RejectedGalleryEntity,
+    TelegraphEntity,
 };
-use crate::ehentai::{EhClient, EhGallery, EhGalleryUrl, GalleryInfo};
+use crate::ehentai::{
+    ArchiveDownloadResult, EhClient, EhGallery, EhGalleryUrl, GalleryInfo, LocalGallery,
+};
+use crate::import::{self, ImportSource};
+use crate::rules::Rejection;
 use crate::tags::EhTagTransDB;
+use crate::template::{self, GalleryContext, TagGroup};
+
+/// 试运行时，尚未发布的文章使用的占位地址
+const DRY_RUN_URL: &str = "https://telegra.ph/";
+/// 批量修改频道消息的间隔，频道每分钟最多修改 20 条消息左右
+const EDIT_INTERVAL: Duration = Duration::from_secs(3);
+/// 不满足上传规则的画廊在这段时间内不再检查，收藏数等信息可能会变化，因此不会永久跳过
+const REJECTION_TTL: Duration = Duration::from_secs(24 * 60 * 60);
 
 #[derive(Debug, Clone)]
 pub struct ExloliUploader {
     ehentai: EhClient,
     telegraph: Telegraph,
     bot: Bot,
-    config: Config,
+    config: SharedConfig,
     trans: EhTagTransDB,
+    /// 试运行模式，开启后只获取画廊并生成消息，不上传图片、不发布文章、不发送消息、不写入数据库
+    dry_run: bool,
+    /// 试运行结果的接收者，为空时输出到标准输出
+    dry_run_chat: Option<ChatId>,
+    /// 同一时间只允许运行一个重新渲染任务
+    rerender_lock: Arc<Mutex<()>>,
+}
+
+/// 重新渲染频道消息的范围
+#[derive(Debug, Clone, PartialEq)]
+pub enum RerenderFilter {
+    /// 所有消息
+    All,
+    /// 在指定日期范围内（包含两端）发布的消息
+    Date(NaiveDate, NaiveDate),
+    /// 指定画廊的消息
+    Gallery(Vec<i32>),
+}
+
+impl FromStr for RerenderFilter {
+    type Err = String;
+
+    /// 支持 all、开始日期 [结束日期]、画廊 ID 列表
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let args = s.split([' ', ',']).filter(|s| !s.is_empty()).collect::<Vec<_>>();
+        if args.is_empty() || args == ["all"] {
+            return Ok(Self::All);
+        }
+        let dates = args
+            .iter()
+            .map(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d"))
+            .collect::<Result<Vec<_>, _>>();
+        match dates.as_deref() {
+            Ok([start]) => return Ok(Self::Date(*start, Utc::now().date_naive())),
+            Ok([start, end]) => return Ok(Self::Date(*start, *end)),
+            _ => (),
+        }
+        match args.iter().map(|s| s.parse()).collect::<Result<Vec<_>, _>>() {
+            Ok(ids) => Ok(Self::Gallery(ids)),
+            Err(_) => Err(format!(
+                "无法解析 {s}，可以使用 all、开始日期 [结束日期]（例如 2024-01-01 2024-01-31）或者画廊 ID 列表"
+            )),
+        }
+    }
+}
+
+/// 重新渲染频道消息的进度
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RerenderProgress {
+    pub total: usize,
+    pub done: usize,
+    /// 内容有变化而被修改的消息数量
+    pub edited: usize,
+    pub failed: usize,
+}
+
+impl std::fmt::Display for RerenderProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "已处理 {}/{}，修改 {} 条，失败 {} 条",
+            self.done, self.total, self.edited, self.failed
+        )
+    }
 }
 
 impl ExloliUploader {
     pub async fn new(
-        config: Config,
+        config: SharedConfig,
         ehentai: EhClient,
         bot: Bot,
         trans: EhTagTransDB,
     ) -> Result<Self> {
-        let telegraph = Telegraph::new(&config.telegraph.author_name)
-            .author_url(&config.telegraph.author_url)
-            .access_token(&config.telegraph.access_token)
+        let telegraph_config = config.get().telegraph;
+        let telegraph = Telegraph::new(&telegraph_config.author_name)
+            .author_url(&telegraph_config.author_url)
+            .access_token(&telegraph_config.access_token)
             .create()
             .await?;
         Ok(Self {
@@ -50,26 +134,38 @@ impl ExloliUploader {
             telegraph,
             bot,
             trans,
+            dry_run: false,
+            dry_run_chat: None,
+            rerender_lock: Default::default(),
         })
     }
 
+    /// 开启试运行模式，结果会发送给 chat，如果 chat 为空，则输出到标准输出
+    pub fn with_dry_run(mut self, chat: Option<ChatId>) -> Self {
+        self.dry_run = true;
+        self.dry_run_chat = chat;
+        self
+    }
+
     /// 每隔 interval 分钟检查一次
     pub async fn start(&self) {
         loop {
             info!("开始扫描 E 站 本子");
             self.check().await;
-            info!("扫描完毕，等待 {:?} 后继续", self.config.interval);
-            time::sleep(self.config.interval).await;
+            let interval = self.config.get().interval;
+            info!("扫描完毕，等待 {:?} 后继续", interval);
+            time::sleep(interval).await;
         }
     }
 
     /// 根据配置文件，扫描前 N 个本子，并进行上传或者更新
     #[tracing::instrument(skip(self))]
-    async fn check(&self) {
+    pub async fn check(&self) {
+        let config = self.config.get();
         let stream = self
             .ehentai
-            .search_iter(&self.config.exhentai.search_params)
-            .take(self.config.exhentai.search_count);
+            .search_iter(&config.exhentai.search_params)
+            .take(config.exhentai.search_count);
         tokio::pin!(stream);
         while let Some(next) = stream.next().await {
             // 错误不要上抛，避免影响后续画廊
@@ -94,6 +190,8 @@ impl ExloliUploader {
     /// 检查指定画廊是否已经上传，如果没有则进行上传
     ///
     /// 为了避免绕晕自己，这次不考虑父子画廊，只要 id 不同就视为新画廊，只要是新画廊就进行上传
+    ///
+    /// check 为 false 时不检查是否已经上传，也不检查上传规则，用于管理员手动上传
     #[tracing::instrument(skip(self))]
     pub async fn try_upload(&self, gallery_url_param: &EhGalleryUrl, check: bool) -> Result<()> {
         if check
@@ -105,44 +203,102 @@ impl ExloliUploader {
             return Ok(());
         }
 
+        // 最近不满足上传规则的画廊，不再重复获取画廊信息
+        let since = Utc::now().naive_utc() - REJECTION_TTL;
+        if check && RejectedGalleryEntity::check(gallery_url_param.id(), since).await? {
+            debug!("画廊 {} 最近不满足上传规则，跳过", gallery_url_param);
+            return Ok(());
+        }
+
         let gallery_data = self.ehentai.get_gallery(gallery_url_param).await?;
+        if check {
+            if let Err(rejection) = self.config.get().rules.check(&gallery_data) {
+                info!("跳过画廊 {} {}：{}", gallery_data.url, gallery_data.title, rejection);
+                if !self.dry_run {
+                    RejectedGalleryEntity::create(gallery_data.url.id(), &rejection.to_string())
+                        .await?;
+                }
+                return Ok(());
+            }
+        }
+        if self.dry_run {
+            let article = TelegraphEntity::get(gallery_data.url.id()).await?;
+            let article_url = article.map(|t| t.url).unwrap_or_else(|| DRY_RUN_URL.to_string());
+            let text = self.create_message_text(&gallery_data, &article_url, None).await?;
+            return self.report_dry_run(&gallery_data, &text).await;
+        }
+        self.publish(&gallery_data, None).await
+    }
+
+    /// 获取画廊并检查是否满足当前的上传规则，不会进行上传
+    pub async fn test_rules(
+        &self,
+        url: &EhGalleryUrl,
+    ) -> Result<(EhGallery, Result<(), Rejection>)> {
+        let gallery = self.ehentai.get_gallery(url).await?;
+        let result = self.config.get().rules.check(&gallery);
+        Ok((gallery, result))
+    }
+
+    /// 从本地文件夹或压缩包导入画廊，画廊信息来自 E 站或者元数据文件，页面则使用导入的图片
+    #[tracing::instrument(skip(self, gallery_data, source))]
+    pub async fn import(&self, mut gallery_data: EhGallery, source: &ImportSource) -> Result<()> {
+        let (local, hashes) = LocalGallery::from_files(source.files().to_vec()).await?;
+        if !gallery_data.pages.is_empty() && gallery_data.pages.len() != hashes.len() {
+            warn!("导入的图片数量 {} 和画廊页数 {} 不一致", hashes.len(), gallery_data.pages.len());
+        }
+        gallery_data.pages = import::pages_of(&gallery_data.url, &hashes)?;
+        info!("导入画廊：{} {}", gallery_data.url, gallery_data.title);
+
+        if self.dry_run {
+            let text = self.create_message_text(&gallery_data, DRY_RUN_URL, None).await?;
+            return self.report_dry_run(&gallery_data, &text).await;
+        }
+        self.publish(&gallery_data, Some(&local)).await
+    }
+
+    /// 上传图片、发布文章、发送消息，并将画廊信息写入数据库
+    ///
+    /// imported 为导入的本地图片，此时不会从 E 站下载图片
+    async fn publish(
+        &self,
+        gallery_data: &EhGallery,
+        imported: Option<&LocalGallery>,
+    ) -> Result<()> {
         // 上传图片、发布文章
-        let catbox_album_url = self.upload_gallery_image(&gallery_data).await?;
-        let article = self.publish_telegraph_article(&gallery_data).await?;
+        let catbox_album_url = self.upload_gallery_image(gallery_data, imported).await?;
+        let article = self.publish_telegraph_article(gallery_data).await?;
         // 发送消息
         let text = self
-            .create_message_text(
-                &gallery_data,
-                &article.url,
-                catbox_album_url.as_deref(),
-            )
+            .create_message_text(gallery_data, &article.url, catbox_album_url.as_deref())
             .await?;
+        let hash = text_hash(&text);
         // FIXME: 此处没有考虑到父画廊没有上传，但是父父画廊上传过的情况
         // 不过一般情况下画廊应该不会那么短时间内更新多次
         let msg = if let Some(parent) = &gallery_data.parent {
             if let Some(pmsg) = MessageEntity::get_by_gallery(parent.id()).await? {
                 self.bot
-                    .send_message(self.config.telegram.channel_id.clone(), text)
+                    .send_message(self.config.get().telegram.channel_id, text)
                     .reply_to_message_id(MessageId(pmsg.id))
                     .await?
             } else {
                 self.bot
-                    .send_message(self.config.telegram.channel_id.clone(), text)
+                    .send_message(self.config.get().telegram.channel_id, text)
                     .await?
             }
         } else {
             self.bot
-                .send_message(self.config.telegram.channel_id.clone(), text)
+                .send_message(self.config.get().telegram.channel_id, text)
                 .await?
         };
         // 数据入库
         MessageEntity::create(msg.id.0, gallery_data.url.id()).await?;
+        MessageEntity::update_text_hash(msg.id.0, &hash).await?;
         TelegraphEntity::create(gallery_data.url.id(), &article.url).await?;
-        GalleryEntity::create(&gallery_data).await?;
-        // 可选：将 catbox_album_url 保存到数据库，例如一个新的表或者扩展 TelegraphEntity
-        // if let Some(album_url) = catbox_album_url {
-        //     // CatboxAlbumEntity::create(gallery_data.url.id(), &album_url).await?;
-        // }
+        if let Some(album_url) = &catbox_album_url {
+            TelegraphEntity::update_album(gallery_data.url.id(), album_url).await?;
+        }
+        GalleryEntity::create(gallery_data).await?;
 
         Ok(())
     }
@@ -170,33 +326,42 @@ impl ExloliUploader {
             d if d < chrono::Duration::days(14) => 7,
             _ => 14,
         };
-        if check && now.day() % seed != 0 {
+        if check && !now.day().is_multiple_of(seed) {
             return Ok(());
         }
 
         // 检查 tag 和标题是否有变化
         let current_gallery_data = self.ehentai.get_gallery(gallery_url_param).await?;
-        let catbox_album_url = self.upload_gallery_image(&current_gallery_data).await?;
+        let changed = current_gallery_data.tags != entity.tags.0
+            || current_gallery_data.title != entity.title;
+
+        if self.dry_run {
+            if changed {
+                let telegraph = TelegraphEntity::get(current_gallery_data.url.id()).await?;
+                let article_url =
+                    telegraph.map(|t| t.url).unwrap_or_else(|| DRY_RUN_URL.to_string());
+                let text =
+                    self.create_message_text(&current_gallery_data, &article_url, None).await?;
+                self.report_dry_run(&current_gallery_data, &text).await?;
+            }
+            return Ok(());
+        }
 
-        if current_gallery_data.tags != entity.tags.0 || current_gallery_data.title != entity.title
-        {
+        let catbox_album_url = self.upload_gallery_image(&current_gallery_data, None).await?;
+        if let Some(album_url) = &catbox_album_url {
+            TelegraphEntity::update_album(current_gallery_data.url.id(), album_url).await?;
+        }
+
+        if changed {
             let telegraph = TelegraphEntity::get(current_gallery_data.url.id())
                 .await?
-                .unwrap();
+                .context("找不到画廊对应的 telegraph 文章")?;
+            // 没有上传新图片时，继续使用之前的专辑
+            let album_url = catbox_album_url.or(telegraph.album_url);
             let text = self
-                .create_message_text(
-                    &current_gallery_data,
-                    &telegraph.url,
-                    catbox_album_url.as_deref(),
-                )
-                .await?;
-            self.bot
-                .edit_message_text(
-                    self.config.telegram.channel_id.clone(),
-                    MessageId(message.id),
-                    text,
-                )
+                .create_message_text(&current_gallery_data, &telegraph.url, album_url.as_deref())
                 .await?;
+            self.edit_channel_message(message.id, &text).await?;
         }
 
         GalleryEntity::create(&current_gallery_data).await?;
@@ -207,22 +372,25 @@ impl ExloliUploader {
     /// 重新发布指定画廊的文章，并更新消息
     pub async fn republish(&self, gallery: &GalleryEntity, msg: &MessageEntity) -> Result<()> {
         info!("重新发布：{}", msg.id);
+        if self.dry_run {
+            info!("试运行模式，跳过重新发布");
+            return Ok(());
+        }
         let article = self.publish_telegraph_article(gallery).await?;
 
         let eh_gallery_url = gallery.url();
         let gallery_data_for_catbox = self.ehentai.get_gallery(&eh_gallery_url).await?;
-        let catbox_album_url = self.upload_gallery_image(&gallery_data_for_catbox).await?;
+        let catbox_album_url = self.upload_gallery_image(&gallery_data_for_catbox, None).await?;
+        let album_url = match catbox_album_url {
+            Some(album_url) => {
+                TelegraphEntity::update_album(gallery.id, &album_url).await?;
+                Some(album_url)
+            }
+            None => TelegraphEntity::get(gallery.id).await?.and_then(|t| t.album_url),
+        };
 
-        let text = self
-            .create_message_text(gallery, &article.url, catbox_album_url.as_deref())
-            .await?;
-        self.bot
-            .edit_message_text(
-                self.config.telegram.channel_id.clone(),
-                MessageId(msg.id),
-                text,
-            )
-            .await?;
+        let text = self.create_message_text(gallery, &article.url, album_url.as_deref()).await?;
+        self.edit_channel_message(msg.id, &text).await?;
         TelegraphEntity::update(gallery.id, &article.url).await?;
         Ok(())
     }
@@ -231,10 +399,140 @@ impl ExloliUploader {
     pub async fn check_telegraph(&self, url: &str) -> Result<bool> {
         Ok(Client::new().head(url).send().await?.status() != StatusCode::NOT_FOUND)
     }
+
+    /// 使用当前的翻译、显示设置和模板重新渲染频道消息，只修改内容有变化的消息
+    ///
+    /// 进度会通过 progress 发送，每次修改消息之后会等待一段时间，以免触发频率限制
+    pub async fn rerender(
+        &self,
+        filter: &RerenderFilter,
+        progress: watch::Sender<RerenderProgress>,
+    ) -> Result<RerenderProgress> {
+        let _lock =
+            self.rerender_lock.try_lock().map_err(|_| anyhow!("已经有一个重新渲染任务正在运行"))?;
+        let messages = match filter {
+            RerenderFilter::All => {
+                let end = NaiveDate::from_ymd_opt(9999, 12, 31).unwrap();
+                MessageEntity::list(NaiveDate::default(), end).await?
+            }
+            RerenderFilter::Date(start, end) => MessageEntity::list(*start, *end).await?,
+            RerenderFilter::Gallery(ids) => {
+                let mut messages = vec![];
+                for id in ids {
+                    match MessageEntity::get_by_gallery(*id).await? {
+                        Some(msg) => messages.push(msg),
+                        None => warn!("找不到画廊 {} 的消息", id),
+                    }
+                }
+                messages
+            }
+        };
+        info!("重新渲染 {} 条消息", messages.len());
+
+        let mut state = RerenderProgress { total: messages.len(), ..Default::default() };
+        progress.send_replace(state);
+        for msg in messages {
+            match self.rerender_message(&msg).await {
+                Ok(true) => {
+                    state.edited += 1;
+                    time::sleep(EDIT_INTERVAL).await;
+                }
+                Ok(false) => (),
+                Err(err) => {
+                    error!("重新渲染消息 {} 失败：{:?}", msg.id, err);
+                    state.failed += 1;
+                }
+            }
+            state.done += 1;
+            progress.send_replace(state);
+        }
+        info!("重新渲染完成：{}", state);
+        Ok(state)
+    }
 }
 
 impl ExloliUploader {
-    async fn upload_gallery_image(&self, gallery: &EhGallery) -> Result<Option<String>> {
+    /// 重新渲染一条消息，返回是否修改了消息
+    ///
+    /// 没有记录 hash 的旧消息无法判断内容是否变化，因此总是修改
+    async fn rerender_message(&self, msg: &MessageEntity) -> Result<bool> {
+        let gallery = match GalleryEntity::get(msg.gallery_id).await? {
+            Some(gallery) if !gallery.deleted => gallery,
+            _ => return Ok(false),
+        };
+        let telegraph = match TelegraphEntity::get(gallery.id).await? {
+            Some(telegraph) => telegraph,
+            None => return Ok(false),
+        };
+        // NOTE: 记录专辑地址之前发布的消息无法得知原本的专辑链接，重新渲染后不再显示专辑链接
+        if telegraph.album_url.is_none() {
+            debug!("画廊 {} 没有记录专辑地址，渲染时不包含专辑链接", gallery.id);
+        }
+        let text = self
+            .create_message_text(&gallery, &telegraph.url, telegraph.album_url.as_deref())
+            .await?;
+        if msg.text_hash.as_deref() == Some(text_hash(&text).as_str()) {
+            return Ok(false);
+        }
+        if self.dry_run {
+            info!("[试运行] 需要修改消息：{}\n{}", msg.id, text);
+            return Ok(true);
+        }
+        self.edit_channel_message(msg.id, &text).await?;
+        Ok(true)
+    }
+
+    /// 修改频道中的消息，并记录新的消息内容
+    ///
+    /// 遇到频率限制时会等待后重试，消息内容没有变化时不视为错误
+    async fn edit_channel_message(&self, id: i32, text: &str) -> Result<()> {
+        let channel_id = self.config.get().telegram.channel_id;
+        loop {
+            let result = self.bot.edit_message_text(channel_id.clone(), MessageId(id), text).await;
+            match result {
+                Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => break,
+                Err(RequestError::RetryAfter(duration)) => {
+                    warn!("修改消息触发频率限制，等待 {:?}", duration);
+                    time::sleep(duration).await;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        MessageEntity::update_text_hash(id, &text_hash(text)).await?;
+        Ok(())
+    }
+
+    /// 输出试运行的结果，包括渲染后的消息和需要上传的页面
+    async fn report_dry_run(&self, gallery: &EhGallery, text: &str) -> Result<()> {
+        let mut pages = vec![];
+        for page in &gallery.pages {
+            if ImageEntity::get_by_hash(page.hash()).await?.is_none() {
+                pages.push(page.page().to_string());
+            }
+        }
+        let report = format!(
+            "[试运行] {}\n需要上传的页面（{}/{}）：{}",
+            gallery.url(),
+            pages.len(),
+            gallery.pages.len(),
+            pages.join(", ")
+        );
+
+        match self.dry_run_chat {
+            Some(chat) => {
+                self.bot.send_message(chat, escape(&report)).disable_web_page_preview(true).await?;
+                self.bot.send_message(chat, text).await?;
+            }
+            None => println!("{}\n{}\n", report, text),
+        }
+        Ok(())
+    }
+
+    async fn upload_gallery_image(
+        &self,
+        gallery: &EhGallery,
+        imported: Option<&LocalGallery>,
+    ) -> Result<Option<String>> {
         // 收集需要上传的图片
         let mut pages_to_upload = vec![];
         for page in &gallery.pages {
@@ -254,18 +552,84 @@ impl ExloliUploader {
 
 
         let client = self.ehentai.clone();
+        let config = self.config.get();
         let catbox = CatboxUploader::new(
-            &self.config.catbox.api_url,
-            &self.config.catbox.userhash,
+            &config.catbox.api_url,
+            &config.catbox.userhash,
         );
 
         // 上传的文件短链接列表
         let mut uploaded_file_names = vec![];
 
+        // 优先使用 H@H 下载到本地的原图，可以节省 E 站的图片配额
+        let hath = match &config.exhentai.hath_dir {
+            Some(dir) if imported.is_none() && !pages_to_upload.is_empty() => {
+                LocalGallery::find(dir, gallery.url.id()).await.unwrap_or_else(|err| {
+                    error!("读取本地画廊失败: {}", err);
+                    None
+                })
+            }
+            _ => None,
+        }
+        .unwrap_or_default();
+        // 本地没有时，如果需要上传的图片较多，则直接下载整个归档，避免逐页请求
+        let archive = match &config.exhentai.archive_download {
+            Some(opt)
+                if imported.is_none()
+                    && hath.is_empty()
+                    && pages_to_upload.len() >= opt.min_pages =>
+            {
+                match client.download_archive(gallery, opt.kind, opt.max_cost).await {
+                    Ok(ArchiveDownloadResult::Downloaded(local)) => Some(local),
+                    Ok(result) => {
+                        info!("不下载归档，改为逐页下载: {:?}", result);
+                        None
+                    }
+                    Err(err) => {
+                        error!("下载归档失败，改为逐页下载: {}", err);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        let local = imported.or(archive.as_ref()).unwrap_or(&hath);
+        if !local.is_empty() {
+            info!("本地画廊图片数: {}", local.len());
+        }
+
         // 上传图片
         for page in pages_to_upload {
-            let rst = client.get_image_url(&page).await?;
-            let mut suffix = rst.1.split('.').last().unwrap_or("jpg");
+            // 同一个画廊中可能有重复的图片，此时前面的页面已经上传过了
+            if let Some(img) = ImageEntity::get_by_hash(page.hash()).await? {
+                PageEntity::create(page.gallery_id(), page.page(), img.id).await?;
+                continue;
+            }
+            let (fileindex, suffix, file_bytes) = match local.get(page.hash()) {
+                Some(path) => {
+                    // 导入的图片不一定存在于 E 站，归档则是为了减少请求，因此都不去获取 fileindex
+                    let fileindex = if imported.is_some() || archive.is_some() {
+                        None
+                    } else {
+                        Some(client.get_fileindex(&page).await?)
+                    };
+                    let suffix = path.extension().and_then(|s| s.to_str()).unwrap_or("jpg");
+                    let suffix = suffix.to_lowercase();
+                    let path = path.to_owned();
+                    let file_bytes =
+                        tokio::task::spawn_blocking(move || std::fs::read(path)).await??;
+                    debug!("已读取本地文件: {}", page.page());
+                    (fileindex, suffix, file_bytes)
+                }
+                None => {
+                    let rst = client.get_image_url(&page).await?;
+                    let suffix = rst.1.rsplit('.').next().unwrap_or("jpg").to_string();
+                    let file_bytes = reqwest::get(&rst.1).await?.bytes().await?.to_vec();
+                    debug!("已下载: {}", page.page());
+                    (Some(rst.0), suffix, file_bytes)
+                }
+            };
+            let mut suffix = suffix.as_str();
             // 检查是否为 webp 格式，若是则将后缀修改为 jpg
             if suffix == "webp" {
                 suffix = "jpg";
@@ -275,8 +639,6 @@ impl ExloliUploader {
             }
 
             let file_name_on_catbox = format!("{}.{}", page.hash(), suffix);
-            let file_bytes = reqwest::get(&rst.1).await?.bytes().await?.to_vec();
-            debug!("已下载: {}", page.page());
 
             // 调用 CatboxUploader 上传文件
             match catbox
@@ -286,13 +648,19 @@ impl ExloliUploader {
                 Ok(file_url_on_catbox) => {
                     debug!("已上传: {}", page.page());
                     // 记录到数据库
-                    ImageEntity::create(rst.0, page.hash(), &file_url_on_catbox).await?;
-                    PageEntity::create(page.gallery_id(), page.page(), rst.0).await?;
+                    let image_id = match fileindex {
+                        Some(id) => {
+                            ImageEntity::create(id, page.hash(), &file_url_on_catbox).await?;
+                            id
+                        }
+                        None => ImageEntity::create_local(page.hash(), &file_url_on_catbox).await?,
+                    };
+                    PageEntity::create(page.gallery_id(), page.page(), image_id).await?;
 
                     // 只收集文件的短链接（文件名）
                     let file_short_name = file_url_on_catbox
-                        .split('/')
-                        .last()
+                        .rsplit('/')
+                        .next()
                         .unwrap_or(""); // 获取短链接部分，如：4b71m5.webp
                     if !file_short_name.is_empty() {
                         uploaded_file_names.push(file_short_name.to_string());
@@ -308,7 +676,7 @@ impl ExloliUploader {
         // 如果有新上传的文件，则创建专辑
         if !uploaded_file_names.is_empty() {
             let album_title = gallery.title_jp(); // 优先使用日文标题
-            let album_desc = self.config.telegraph.author_name.clone(); // 描述为作者名
+            let album_desc = config.telegraph.author_name.clone(); // 描述为作者名
 
             match catbox
                 .create_album(
@@ -352,18 +720,12 @@ impl ExloliUploader {
         gallery: &T,
     ) -> Result<telegraph_rs::Page> {
         let images = ImageEntity::get_by_gallery_id(gallery.url().id()).await?;
+        let images = images.iter().map(|img| img.url()).collect::<Vec<_>>();
+        let cover = images.get(gallery.cover()).filter(|_| gallery.cover() != 0);
 
-        let mut html = String::new();
-        if gallery.cover() != 0 && gallery.cover() < images.len() {
-            html.push_str(&format!(
-                r#"<img src="{}">"#,
-                images[gallery.cover()].url()
-            ))
-        }
-        for img in images {
-            html.push_str(&format!(r#"<img src="{}">"#, img.url()));
-        }
-        html.push_str(&format!("<p>ᴘᴀɢᴇꜱ : {}</p>", gallery.pages()));
+        let ctx = self.gallery_context(gallery).await?;
+        let file = self.config.get().telegraph.article_template;
+        let html = template::render_article(file.as_deref(), &ctx, &images, cover.map(|s| &**s))?;
 
         let node = html_to_node(&html);
         // 文章标题优先使用日文
@@ -378,35 +740,65 @@ impl ExloliUploader {
         article_url: &str,
         catbox_album_url: Option<&str>,
     ) -> Result<String> {
-        // 首先，将 tag 翻译
-        let re = Regex::new("[-/· ]").unwrap();
-        let tags = self.trans.trans_tags(gallery.tags());
-        let mut text = String::new();
-        text.push_str(&format!("<b>{}</b>\n\n", gallery.title_jp()));
-        for (ns, tag) in tags {
-            let tag = tag
+        let ctx = self.gallery_context(gallery).await?;
+        let file = self.config.get().telegram.message_template;
+        template::render_message(file.as_deref(), &ctx, article_url, catbox_album_url)
+    }
+
+    /// 生成模板中使用的画廊信息
+    async fn gallery_context<T: GalleryInfo>(&self, gallery: &T) -> Result<GalleryContext> {
+        let url = gallery.url();
+        let poll = PollEntity::get_by_gallery(url.id()).await?;
+        Ok(GalleryContext {
+            id: url.id(),
+            url: url.url(),
+            title: gallery.title(),
+            title_jp: gallery.title_jp(),
+            tags: self.tag_groups(gallery.tags()),
+            pages: gallery.pages(),
+            favorite: gallery.favorite(),
+            posted: gallery.posted().map(|t| t.format("%Y-%m-%d %H:%M").to_string()),
+            score: poll.map(|p| p.score * 100.),
+        })
+    }
+
+    /// 按照显示设置翻译 tag，并选择需要显示的 namespace
+    fn tag_groups(&self, tags: &IndexMap<String, Vec<String>>) -> Vec<TagGroup> {
+        let display = self.config.get().telegram.display;
+        let tags = self.trans.resolve_tags(tags);
+        // 指定了 namespace 时，按照指定的顺序显示
+        let namespaces = if display.namespaces.is_empty() {
+            tags.keys().collect::<Vec<_>>()
+        } else {
+            display.namespaces.iter().filter(|ns| tags.contains_key(*ns)).collect()
+        };
+        let max = display.max_tags.unwrap_or(usize::MAX);
+        let mut groups = vec![];
+        for ns in namespaces {
+            let namespace = match display.language {
+                TagLanguage::Original => ns.clone(),
+                _ => with_original(&self.trans.trans_namespace(ns), ns, display.language),
+            };
+            let names = tags[ns]
                 .iter()
-                .map(|s| format!("#{}", re.replace_all(s, "_")))
-                .collect::<Vec<_>>()
-                .join(" ");
-            text.push_str(&format!("⁣⁣⁣⁣　<code>{}</code>: <i>{}</i>\n", ns, tag))
-        }
-        text.push_str(&format!(
-            "\n<b>〔 <a href=\"{}\">即 時 預 覽</a> 〕</b>/",
-            article_url
-        ));
-        text.push_str(&format!(
-            "<b>〔 <a href=\"{}\">来 源</a> 〕</b>", // 在这里结束，如果后面有专辑链接则会加上 /
-            gallery.url().url()
-        ));
-
-        if let Some(album_url) = catbox_album_url {
-            text.push_str(&format!(
-                "/<b>〔 <a href=\"{}\">專 輯</a> 〕</b>",
-                album_url
-            ));
-        }
-        Ok(text)
+                .take(max)
+                .map(|t| {
+                    let names = match display.language {
+                        TagLanguage::Original => vec![t.clone()],
+                        _ => self.trans.trans(ns, t),
+                    };
+                    let names = names.iter().map(|s| tag_text(s, display.hashtag));
+                    with_original(&names.collect::<Vec<_>>().join(" "), t, display.language)
+                })
+                .collect();
+            groups.push(TagGroup {
+                namespace,
+                raw: ns.clone(),
+                tags: names,
+                more: tags[ns].len() > max,
+            });
+        }
+        groups
     }
 }
 
@@ -462,3 +854,26 @@ impl ExloliUploader {
         Ok(())
     }
 }
+
+/// 消息内容的 hash，用于判断重新渲染后消息是否有变化
+fn text_hash(text: &str) -> String {
+    Sha1::digest(text).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn tag_text(tag: &str, hashtag: bool) -> String {
+    if hashtag {
+        template::hashtag(tag)
+    } else {
+        tag.to_string()
+    }
+}
+
+/// 同时显示两种语言时，在翻译后面用括号注明原名，没有翻译时只显示原名
+fn with_original(text: &str, original: &str, language: TagLanguage) -> String {
+    let untranslated = text == original || text == tag_text(original, true);
+    if language == TagLanguage::Both && !untranslated {
+        format!("{}（{}）", text, original)
+    } else {
+        text.to_string()
+    }
+}
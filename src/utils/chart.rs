@@ -0,0 +1,48 @@
+use std::io::Cursor;
+
+use image::{ImageFormat, ImageResult, Rgb, RgbImage};
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 400;
+const MARGIN: u32 = 40;
+
+const BACKGROUND: Rgb<u8> = Rgb([255, 255, 255]);
+const GRID: Rgb<u8> = Rgb([230, 230, 230]);
+const AXIS: Rgb<u8> = Rgb([120, 120, 120]);
+const BAR: Rgb<u8> = Rgb([66, 133, 244]);
+
+/// 绘制一个柱状图并编码为 PNG，图中没有文字，数值需要在图片说明中给出
+pub fn bar_chart(values: &[i32]) -> ImageResult<Vec<u8>> {
+    let mut img = RgbImage::from_pixel(WIDTH, HEIGHT, BACKGROUND);
+    let (left, right, top, bottom) = (MARGIN, WIDTH - MARGIN, MARGIN, HEIGHT - MARGIN);
+
+    // 每 25% 一条横向网格线
+    for i in 1..4 {
+        let y = bottom - (bottom - top) * i / 4;
+        fill(&mut img, left, y, right, y + 1, GRID);
+    }
+
+    let max = values.iter().copied().max().unwrap_or(0).max(1) as u32;
+    let slot = (right - left) / values.len().max(1) as u32;
+    for (i, &value) in values.iter().enumerate() {
+        let height = (bottom - top) * value.max(0) as u32 / max;
+        let x = left + slot * i as u32 + slot / 8;
+        fill(&mut img, x, bottom - height, x + slot * 3 / 4, bottom, BAR);
+    }
+
+    fill(&mut img, left, bottom, right, bottom + 2, AXIS);
+    fill(&mut img, left - 2, top, left, bottom + 2, AXIS);
+
+    let mut buf = Cursor::new(vec![]);
+    img.write_to(&mut buf, ImageFormat::Png)?;
+    Ok(buf.into_inner())
+}
+
+/// 填充 [x0, x1) × [y0, y1) 的矩形区域
+fn fill(img: &mut RgbImage, x0: u32, y0: u32, x1: u32, y1: u32, color: Rgb<u8>) {
+    for y in y0..y1.min(img.height()) {
+        for x in x0..x1.min(img.width()) {
+            img.put_pixel(x, y, color);
+        }
+    }
+}
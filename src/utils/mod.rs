@@ -1,9 +1,11 @@
 use std::borrow::Cow;
+use std::cmp::Ordering;
 
+pub mod chart;
 pub mod html;
 
 /// 左填充空格
-pub fn pad_left(s: &str, len: usize) -> Cow<str> {
+pub fn pad_left(s: &str, len: usize) -> Cow<'_, str> {
     let width = unicode_width::UnicodeWidthStr::width(s);
     if width >= len {
         Cow::Borrowed(s)
@@ -11,3 +13,50 @@ pub fn pad_left(s: &str, len: usize) -> Cow<str> {
         Cow::Owned(" ".repeat(len - width) + s)
     }
 }
+
+/// 按自然顺序比较字符串，其中的数字部分按数值比较，例如 2.jpg 排在 10.jpg 之前
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        match (a.chars().next(), b.chars().next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (na, ra) = split_digits(a);
+                let (nb, rb) = split_digits(b);
+                // 先去掉前导零比较长度，再按字典序比较，避免数字过长时溢出
+                let (ta, tb) = (na.trim_start_matches('0'), nb.trim_start_matches('0'));
+                let ord = ta.len().cmp(&tb.len()).then_with(|| ta.cmp(tb));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                (a, b) = (ra, rb);
+            }
+            (Some(x), Some(y)) => {
+                let ord = x.to_lowercase().cmp(y.to_lowercase());
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                (a, b) = (&a[x.len_utf8()..], &b[y.len_utf8()..]);
+            }
+        }
+    }
+}
+
+fn split_digits(s: &str) -> (&str, &str) {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s.split_at(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn natural_order() {
+        let mut names = vec!["10.jpg", "2.jpg", "page_001.png", "1.jpg", "02.jpg", "Page_10.png"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, ["1.jpg", "2.jpg", "02.jpg", "10.jpg", "page_001.png", "Page_10.png"]);
+    }
+}